@@ -0,0 +1,66 @@
+//! Passes the versions of the dependencies locked in Cargo.lock to `--env-dump` as `DETAT_DEPENDENCIES`
+//! (`name=version,...`, empty without a Cargo.lock).
+
+use std::{env, fs, path::Path};
+
+fn main() {
+    let lock = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lock.display());
+    let dependencies = fs::read_to_string(&lock).map(|lock| dependencies(&lock)).unwrap_or_default();
+    println!("cargo:rustc-env=DETAT_DEPENDENCIES={}", dependencies.join(","));
+}
+
+/// A `[[package]]` of Cargo.lock.
+struct Package {
+    name: String,
+    version: String,
+    dependencies: Vec<String>,
+}
+
+/// Returns `name=version` for each direct dependency of this package in the Cargo.lock `lock`.
+fn dependencies(lock: &str) -> Vec<String> {
+    let packages: Vec<Package> = lock.split("[[package]]").skip(1).map(parse_package).collect();
+    let name = env::var("CARGO_PKG_NAME").unwrap();
+    let root = match packages.iter().find(|p| p.name == name) {
+        Some(root) => root,
+        None => return Vec::new(),
+    };
+    root.dependencies
+        .iter()
+        .filter_map(|dependency| {
+            // `name`, or `name version` (followed by the source in old lock files) if several versions are locked.
+            let mut words = dependency.split(' ');
+            let name = words.next()?;
+            let version = match words.next() {
+                Some(version) => version,
+                None => &packages.iter().find(|p| p.name == name)?.version,
+            };
+            Some(format!("{}={}", name, version))
+        })
+        .collect()
+}
+
+fn parse_package(block: &str) -> Package {
+    let mut package = Package { name: String::new(), version: String::new(), dependencies: Vec::new() };
+    let mut in_dependencies = false;
+    for line in block.lines().map(str::trim) {
+        if in_dependencies {
+            if line == "]" {
+                in_dependencies = false;
+            } else {
+                package.dependencies.push(unquote(line.trim_end_matches(',')));
+            }
+        } else if let Some(name) = line.strip_prefix("name = ") {
+            package.name = unquote(name);
+        } else if let Some(version) = line.strip_prefix("version = ") {
+            package.version = unquote(version);
+        } else if line == "dependencies = [" {
+            in_dependencies = true;
+        }
+    }
+    package
+}
+
+fn unquote(s: &str) -> String {
+    s.trim_matches('"').to_string()
+}
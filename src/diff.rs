@@ -0,0 +1,219 @@
+//! Line diffs in the unified format without context lines, computed with Myers' O(ND) algorithm in linear space.
+
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Writes the lines which differ between `a` and `b` as unified diff hunks without context (as `diff -U0` does),
+/// with the `-` and `+` lines wrapped in the `(del, add, reset)` escapes.
+pub fn write_line_diff<W: Write>(a: &str, b: &str, (del, add, reset): (&str, &str, &str), w: &mut W) -> io::Result<()> {
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+    let mut edits = Vec::with_capacity(a.len() + b.len());
+    diff(&a, &b, &mut edits);
+    let (mut i, mut j) = (0, 0);
+    let mut edits = edits.into_iter().peekable();
+    while let Some(edit) = edits.next() {
+        if edit == Edit::Keep {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (deleted, inserted) = (i, j);
+        let mut hunk = vec![edit];
+        while let Some(edit) = edits.next_if(|&edit| edit != Edit::Keep) {
+            hunk.push(edit);
+        }
+        let deletes = hunk.iter().filter(|&&edit| edit == Edit::Delete).count();
+        let inserts = hunk.len() - deletes;
+        writeln!(w, "@@ -{} +{} @@", range(deleted, deletes), range(inserted, inserts))?;
+        for line in &a[i..i + deletes] {
+            writeln!(w, "{}-{}{}", del, line, reset)?;
+        }
+        for line in &b[j..j + inserts] {
+            writeln!(w, "{}+{}{}", add, line, reset)?;
+        }
+        i += deletes;
+        j += inserts;
+    }
+    Ok(())
+}
+
+/// Formats the `len` lines after the first `start` ones as in a unified hunk header (which names the line before
+/// an empty range).
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Appends the shortest edit script from `a` to `b` to `edits`, splitting the problem at the middle snake.
+fn diff(a: &[&str], b: &[&str], edits: &mut Vec<Edit>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    edits.extend(std::iter::repeat_n(Edit::Keep, prefix));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+    match middle_snake(a, b) {
+        Some((x, y)) => {
+            diff(&a[..x], &b[..y], edits);
+            diff(&a[x..], &b[y..], edits);
+        }
+        None => {
+            edits.extend(std::iter::repeat_n(Edit::Delete, a.len()));
+            edits.extend(std::iter::repeat_n(Edit::Insert, b.len()));
+        }
+    }
+    edits.extend(std::iter::repeat_n(Edit::Keep, suffix));
+}
+
+/// Returns where the forward and backward searches of a shortest edit script from `a` to `b` meet, or `None` if
+/// there is nothing to split (one side is empty or no line is common).
+fn middle_snake(a: &[&str], b: &[&str]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    if n == 0 || m == 0 {
+        return None;
+    }
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d + 1;
+    // The furthest x reached on each diagonal k = x - y, from the start (forward) and from the end (backward).
+    let mut forward = vec![-1; 2 * offset as usize + 1];
+    let mut backward = forward.clone();
+    forward[offset as usize + 1] = 0;
+    backward[offset as usize + 1] = 0;
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    for d in 0..max_d {
+        for k in (-d..=d).step_by(2) {
+            let i = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && forward[i - 1] < forward[i + 1]) {
+                forward[i + 1]
+            } else {
+                forward[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[i] = x;
+            if odd && x <= n && y <= m {
+                let j = offset + delta - k;
+                if (0..backward.len() as isize).contains(&j)
+                    && backward[j as usize] != -1
+                    && x >= n - backward[j as usize]
+                {
+                    return split(x, y, n, m);
+                }
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let i = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && backward[i - 1] < backward[i + 1]) {
+                backward[i + 1]
+            } else {
+                backward[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[i] = x;
+            if !odd && x <= n && y <= m {
+                let j = offset + delta - k;
+                if (0..forward.len() as isize).contains(&j) && forward[j as usize] != -1 && forward[j as usize] >= n - x
+                {
+                    let x = forward[j as usize];
+                    return split(x, x - (delta - k), n, m);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Returns the split point `(x, y)` unless it would leave one of the halves as large as the whole.
+fn split(x: isize, y: isize, n: isize, m: isize) -> Option<(usize, usize)> {
+    if (x, y) == (0, 0) || (x, y) == (n, m) {
+        None
+    } else {
+        Some((x as usize, y as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_diff(a: &str, b: &str) -> String {
+        let mut w = Vec::new();
+        write_line_diff(a, b, ("", "", ""), &mut w).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+
+    /// Applies the edit script to `a`, checking it yields `b`, and returns the number of changed lines.
+    fn check_edits(a: &[&str], b: &[&str]) -> usize {
+        let mut edits = Vec::new();
+        diff(a, b, &mut edits);
+        let (mut i, mut j) = (0, 0);
+        for &edit in &edits {
+            match edit {
+                Edit::Keep => {
+                    assert_eq!(a[i], b[j]);
+                    i += 1;
+                    j += 1;
+                }
+                Edit::Delete => i += 1,
+                Edit::Insert => j += 1,
+            }
+        }
+        assert_eq!((i, j), (a.len(), b.len()));
+        edits.iter().filter(|&&edit| edit != Edit::Keep).count()
+    }
+
+    #[test]
+    fn identical() {
+        assert_eq!(line_diff("a\nb\nc\n", "a\nb\nc\n"), "");
+        assert_eq!(line_diff("", ""), "");
+    }
+
+    #[test]
+    fn insertion() {
+        assert_eq!(line_diff("a\nb\nc\nd\n", "a\nx\nb\nc\nd\n"), "@@ -1,0 +2 @@\n+x\n");
+        assert_eq!(line_diff("a\n", "x\na\n"), "@@ -0,0 +1 @@\n+x\n");
+    }
+
+    #[test]
+    fn removal() {
+        assert_eq!(line_diff("a\nb\nc\nd\n", "a\nc\nd\n"), "@@ -2 +1,0 @@\n-b\n");
+    }
+
+    #[test]
+    fn change() {
+        assert_eq!(
+            line_diff("a\nb\nc\nd\n", "a\nB\nC\nd\ne\n"),
+            "@@ -2,2 +2,2 @@\n-b\n-c\n+B\n+C\n@@ -4,0 +5 @@\n+e\n"
+        );
+    }
+
+    #[test]
+    fn shortest_edit_scripts() {
+        assert_eq!(check_edits(&["a", "b", "c", "a", "b", "b", "a"], &["c", "b", "a", "b", "a", "c"]), 5);
+        assert_eq!(check_edits(&["x", "a", "y", "b", "z"], &["a", "p", "b", "q"]), 5);
+        assert_eq!(check_edits(&["a", "b"], &["c", "d", "e"]), 5);
+        assert_eq!(check_edits(&["a"; 10], &["a"; 7]), 3);
+        let a: Vec<String> = (0..200).map(|i| (i % 7).to_string()).collect();
+        let b: Vec<String> = (0..180).map(|i| (i % 5).to_string()).collect();
+        let (a, b): (Vec<&str>, Vec<&str>) =
+            (a.iter().map(|s| s.as_str()).collect(), b.iter().map(|s| s.as_str()).collect());
+        check_edits(&a, &b);
+    }
+}
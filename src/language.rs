@@ -0,0 +1,83 @@
+//! A rough language guess from the dominant script and, for Latin text, common stop words.
+
+const STOP_WORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "of", "to", "is", "in", "that", "it", "with", "for"]),
+    ("de", &["der", "die", "und", "das", "ist", "nicht", "mit", "ein", "zu", "den"]),
+    ("fr", &["le", "la", "les", "et", "est", "des", "une", "dans", "pas", "que"]),
+    ("es", &["el", "los", "las", "y", "es", "una", "por", "con", "para", "del"]),
+    ("it", &["il", "che", "di", "gli", "una", "sono", "per", "non", "della", "con"]),
+    ("pt", &["o", "os", "e", "uma", "com", "não", "para", "do", "da", "em"]),
+    ("nl", &["de", "het", "een", "en", "van", "is", "niet", "dat", "met", "op"]),
+];
+
+/// Returns an ISO 639-1 code for the language `s` is most likely written in.
+pub fn detect(s: &str) -> Option<&'static str> {
+    let mut counts = [0usize; 10];
+    for c in s.chars() {
+        let i = match c as u32 {
+            0x3040..=0x30FF => 0,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => 1,
+            0x4E00..=0x9FFF => 2,
+            0x0400..=0x04FF => 3,
+            0x0370..=0x03FF => 4,
+            0x0600..=0x06FF => 5,
+            0x0590..=0x05FF => 6,
+            0x0E00..=0x0E7F => 7,
+            0x0900..=0x097F => 8,
+            _ if c.is_alphabetic() => 9,
+            _ => continue,
+        };
+        counts[i] += 1;
+    }
+    let (script, &count) = counts.iter().enumerate().max_by_key(|&(_, n)| n)?;
+    if count == 0 {
+        return None;
+    }
+    // Japanese mixes kana with many kanji, so any notable amount of kana wins over Han.
+    if counts[0] > 0 && counts[0] * 10 >= counts[2] {
+        return Some("ja");
+    }
+    match script {
+        1 => Some("ko"),
+        2 => Some("zh"),
+        3 => Some("ru"),
+        4 => Some("el"),
+        5 => Some("ar"),
+        6 => Some("he"),
+        7 => Some("th"),
+        8 => Some("hi"),
+        _ => detect_latin(s),
+    }
+}
+
+fn detect_latin(s: &str) -> Option<&'static str> {
+    let words: Vec<String> = s.split(|c: char| !c.is_alphabetic()).map(|w| w.to_lowercase()).collect();
+    STOP_WORDS
+        .iter()
+        .map(|(lang, stop_words)| (lang, words.iter().filter(|w| stop_words.contains(&w.as_str())).count()))
+        .filter(|&(_, n)| n > 0)
+        .max_by_key(|&(_, n)| n)
+        .map(|(lang, _)| *lang)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_scripts() {
+        assert_eq!(detect("これは日本語の文章です"), Some("ja"));
+        assert_eq!(detect("中文文本"), Some("zh"));
+        assert_eq!(detect("한국어 텍스트"), Some("ko"));
+        assert_eq!(detect("Привет, мир"), Some("ru"));
+        assert_eq!(detect("123 !?"), None);
+    }
+
+    #[test]
+    fn detect_latin_stop_words() {
+        assert_eq!(detect("The cat and the dog"), Some("en"));
+        assert_eq!(detect("Der Hund und die Katze"), Some("de"));
+        assert_eq!(detect("Le chat et les chiens"), Some("fr"));
+        assert_eq!(detect("Lorem ipsum"), None);
+    }
+}
@@ -1,18 +1,38 @@
 #[macro_use]
 extern crate log;
 
-use chardet::{charset2encoding, detect};
-use encoding::{label::encoding_from_whatwg_label, DecoderTrap};
+mod cp437;
+#[cfg(feature = "legacy-encodings")]
+mod cp850;
+mod diff;
+#[cfg(feature = "language")]
+mod language;
+mod names;
+#[cfg(feature = "tar")]
+mod tar;
+mod utf32;
+mod walk;
+#[cfg(feature = "zip")]
+mod zip;
+
+use chardet::{charset2encoding, UniversalDetector};
+use encoding::{label::encoding_from_whatwg_label, DecoderTrap, EncoderTrap, EncodingRef};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::{
     borrow::Cow,
-    error, fmt,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashSet},
+    env, error, fmt, fs,
     fs::File,
     io,
-    io::{BufWriter, Read, Write},
+    io::{BufWriter, IsTerminal, Read, Write},
     path::{Path, PathBuf},
     process::exit,
     str::FromStr,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
 };
 use structopt::{clap, StructOpt};
 
@@ -33,6 +53,37 @@ impl DetatError {
     pub fn decode(s: Cow<'static, str>) -> DetatError {
         DetatError { kind: DetatErrorKind::Decode(s) }
     }
+
+    pub fn encode(s: Cow<'static, str>) -> DetatError {
+        DetatError { kind: DetatErrorKind::Encode(s) }
+    }
+
+    /// Returns a short name for the class of this error, as written by `--failure-reasons`.
+    pub fn reason(&self) -> &'static str {
+        match self.kind {
+            DetatErrorKind::Io(_) => "io",
+            DetatErrorKind::InvalidOpt(_) => "invalid-option",
+            DetatErrorKind::InvalidInput(ref kind, _) => match kind {
+                InvalidInputErrorKind::IsBinary => "binary",
+                InvalidInputErrorKind::NoEncoding(..) => "no-encoding",
+                InvalidInputErrorKind::LowConfidence(..) => "low-confidence",
+                InvalidInputErrorKind::InvalidFileName(_) => "invalid-file-name",
+                InvalidInputErrorKind::TrailingWhitespace(_) => "trailing-whitespace",
+                InvalidInputErrorKind::Disagreement(..) => "disagreement",
+                InvalidInputErrorKind::MissingBom => "missing-bom",
+                InvalidInputErrorKind::UnexpectedBom => "unexpected-bom",
+                InvalidInputErrorKind::OddLength(_) => "odd-length",
+                InvalidInputErrorKind::InvalidJson => "invalid-json",
+            },
+            DetatErrorKind::Decode(_) => "decode",
+            DetatErrorKind::Encode(_) => "encode",
+        }
+    }
+
+    /// Returns true if the reader of the output has gone away (e.g. `detat file | head`).
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self.kind, DetatErrorKind::Io(ref e) if e.kind() == io::ErrorKind::BrokenPipe)
+    }
 }
 
 #[derive(Debug)]
@@ -42,6 +93,7 @@ pub enum DetatErrorKind {
     InvalidOpt(String),
     InvalidInput(InvalidInputErrorKind, String),
     Decode(Cow<'static, str>),
+    Encode(Cow<'static, str>),
 }
 
 #[derive(Debug)]
@@ -50,6 +102,13 @@ pub enum InvalidInputErrorKind {
     IsBinary,
     NoEncoding(String, String),
     LowConfidence(String, f32, f32),
+    InvalidFileName(Vec<u8>),
+    TrailingWhitespace(usize),
+    Disagreement(String, f32),
+    OddLength(usize),
+    MissingBom,
+    UnexpectedBom,
+    InvalidJson,
 }
 
 impl error::Error for DetatError {
@@ -62,9 +121,10 @@ impl fmt::Display for DetatError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.kind {
             DetatErrorKind::Io(ref e) => e.fmt(f),
+            DetatErrorKind::InvalidOpt(ref m) => f.write_str(m),
             DetatErrorKind::InvalidInput(_, ref m) => f.write_str(m),
             DetatErrorKind::Decode(ref s) => f.write_str(s),
-            _ => f.write_str("internal error"),
+            DetatErrorKind::Encode(ref s) => f.write_str(s),
         }
     }
 }
@@ -102,12 +162,194 @@ impl std::fmt::Debug for MyDecoderTrap {
 
 type DetatResult<T> = Result<T, DetatError>;
 
+#[derive(Clone, Copy, Debug)]
+pub enum NameStyle {
+    Whatwg,
+    Mime,
+    Iconv,
+    Lower,
+    Upper,
+}
+
+impl FromStr for NameStyle {
+    type Err = DetatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "whatwg" => Ok(NameStyle::Whatwg),
+            "mime" => Ok(NameStyle::Mime),
+            "iconv" => Ok(NameStyle::Iconv),
+            "lower" => Ok(NameStyle::Lower),
+            "upper" => Ok(NameStyle::Upper),
+            _ => Err(DetatError::invalid_opt(format!("invalid name style: {}", s))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SampleStrategy {
+    Head,
+    Tail,
+    Spread,
+}
+
+impl FromStr for SampleStrategy {
+    type Err = DetatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "head" => Ok(SampleStrategy::Head),
+            "tail" => Ok(SampleStrategy::Tail),
+            "spread" => Ok(SampleStrategy::Spread),
+            _ => Err(DetatError::invalid_opt(format!("invalid sample strategy: {}", s))),
+        }
+    }
+}
+
+/// A built-in content transform, in the default order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    TrailingWhitespace,
+    LineEnding,
+    PosixNewline,
+}
+
+impl Transform {
+    const ALL: [Transform; 3] = [Transform::TrailingWhitespace, Transform::LineEnding, Transform::PosixNewline];
+
+    fn flag(self) -> &'static str {
+        match self {
+            Transform::TrailingWhitespace => "--fix-trailing-whitespace",
+            Transform::LineEnding => "--line-ending",
+            Transform::PosixNewline => "--posix-newline",
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = DetatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "trailing-whitespace" => Ok(Transform::TrailingWhitespace),
+            "line-ending" => Ok(Transform::LineEnding),
+            "posix-newline" => Ok(Transform::PosixNewline),
+            _ => Err(DetatError::invalid_opt(format!("invalid transform: {}", s))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilenameStyle {
+    Prefix,
+    Banner,
+    Header,
+}
+
+impl FromStr for FilenameStyle {
+    type Err = DetatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "prefix" => Ok(FilenameStyle::Prefix),
+            "banner" => Ok(FilenameStyle::Banner),
+            "header" => Ok(FilenameStyle::Header),
+            _ => Err(DetatError::invalid_opt(format!("invalid filename style: {}", s))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+    Auto,
+}
+
+impl LineEnding {
+    fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::Crlf => "crlf",
+            LineEnding::Cr => "cr",
+            LineEnding::Auto => "auto",
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf | LineEnding::Auto => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = DetatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            "cr" => Ok(LineEnding::Cr),
+            "auto" => Ok(LineEnding::Auto),
+            _ => Err(DetatError::invalid_opt(format!("invalid line ending: {}", s))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PathEncoding {
+    regex: Regex,
+    encoding: String,
+}
+
+impl FromStr for PathEncoding {
+    type Err = DetatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, encoding) =
+            s.rsplit_once('=').ok_or_else(|| DetatError::invalid_opt(format!("expected <REGEX>=<ENCODING>: {}", s)))?;
+        let regex =
+            Regex::new(pattern).map_err(|e| DetatError::invalid_opt(format!("invalid regex: {}: {}", pattern, e)))?;
+        check_label(encoding, false)?;
+        Ok(PathEncoding { regex, encoding: encoding.to_string() })
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfidenceThreshold {
+    label: String,
+    encoding: &'static str,
+    threshold: f32,
+}
+
+impl FromStr for ConfidenceThreshold {
+    type Err = DetatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (label, threshold) = s
+            .rsplit_once('=')
+            .ok_or_else(|| DetatError::invalid_opt(format!("expected <ENCODING>=<CONFIDENCE>: {}", s)))?;
+        let encoding = encoding_from_label(label)
+            .ok_or_else(|| DetatError::invalid_opt(format!("unknown encoding: \"{}\"", label)))?;
+        let threshold = match threshold.parse::<f32>() {
+            Ok(t) if (0.0..=1.0).contains(&t) => t,
+            _ => return Err(DetatError::invalid_opt(format!("confidence must be in 0..1: {}", threshold))),
+        };
+        Ok(ConfidenceThreshold { label: label.to_string(), encoding: encoding.name(), threshold })
+    }
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "detat", about = "cat with chardet")]
 #[structopt(long_version(option_env!("LONG_VERSION").unwrap_or(env!("CARGO_PKG_VERSION"))))]
 #[structopt(setting(clap::AppSettings::ColoredHelp))]
+#[structopt(group = clap::ArgGroup::with_name("json-output").multiple(true))]
 pub struct Opt {
-    #[structopt(name = "PATH", help = "An input file")]
+    #[structopt(name = "PATH", parse(from_os_str), help = "An input file")]
     paths: Vec<PathBuf>,
 
     #[structopt(
@@ -127,7 +369,7 @@ pub struct Opt {
     )]
     fallback_encoding: Option<String>,
 
-    #[structopt(short, long, help = "Show results in a JSON Lines format")]
+    #[structopt(short, long, group = "json-output", help = "Show results in a JSON Lines format")]
     json: bool,
 
     #[structopt(short, long, help = "Show statistics")]
@@ -144,215 +386,3718 @@ pub struct Opt {
         help = "Use this trap handler if errors occur"
     )]
     decoder_trap: MyDecoderTrap,
-}
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct ChardetResult {
-    charset: String,
-    confidence: f32,
-    language: String,
-}
+    #[structopt(
+        long,
+        name = "PREFIX",
+        help = "Write the content of each input to <PREFIX>.NNN (with more digits for 1000 or more inputs)"
+    )]
+    split_output: Option<String>,
 
-impl ChardetResult {
-    pub fn new(charset: String, confidence: f32, language: String) -> ChardetResult {
-        ChardetResult { charset, confidence, language }
-    }
+    #[structopt(long, help = "Reject encoding labels which are not canonical WHATWG names")]
+    strict_label: bool,
 
-    pub fn from_tuple((charset, confidence, language): (String, f32, String)) -> ChardetResult {
-        Self::new(charset, confidence, language)
-    }
-}
+    #[structopt(long, name = "SIZE", help = "Show detected encodings for each window of <SIZE> bytes")]
+    window_detect: Option<usize>,
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct Metadata {
-    chardet: ChardetResult,
-    encoding: String,
-    fallbacked: bool,
-    read_bytes: usize,
-}
+    #[structopt(long, help = "Show numeric metadata fields as strings in JSON")]
+    json_numbers_as_strings: bool,
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct Output {
-    path: Option<String>,
-    metadata: Metadata,
-    content: Option<String>,
-}
+    #[structopt(long, name = "LINES", help = "Show first <LINES> lines as Latin-1 and as decoded side by side")]
+    preview: Option<usize>,
 
-pub struct Detat {
-    confidence_min: f32,
-    fallback_encoding: Option<String>,
-    json: bool,
-    stat: bool,
-    allow_binary: bool,
-    decoder_trap: DecoderTrap,
-}
+    #[structopt(
+        long = "path-encoding",
+        name = "REGEX=ENCODING",
+        number_of_values = 1,
+        help = "Use <ENCODING> for inputs whose path matches <REGEX> (first match wins)"
+    )]
+    path_encodings: Vec<PathEncoding>,
 
-impl Detat {
-    pub fn copy<R: Read, W: Write>(&self, r: &mut R, path: Option<&Path>, w: &mut W) -> DetatResult<Metadata> {
-        let mut bs = Vec::new();
-        let read_bytes = r.read_to_end(&mut bs)?;
-        let chardet = ChardetResult::from_tuple(detect(bs.as_slice()));
-        info!("predicted: {}, confidence: {}, language: {}", chardet.charset, chardet.confidence, chardet.language);
-        if bs.is_empty() {
-            let metadata = Metadata::default();
-            if self.stat && !self.json {
-                self.print_metadata(&metadata, path, w)?;
-            }
-            return Ok(metadata);
-        }
-        let mut fallbacked = false;
-        let charset = chardet.charset.clone();
-        if charset.is_empty() {
-            return if self.allow_binary {
-                let metadata = Metadata { chardet, read_bytes, ..Metadata::default() };
-                if self.stat {
-                    if !self.json {
-                        self.print_metadata(&metadata, path, w)?;
-                    }
-                } else {
-                    w.write_all(&bs)?;
-                }
-                Ok(metadata)
-            } else {
-                Err(DetatError::invalid_input(InvalidInputErrorKind::IsBinary, "Input is binary".to_string()))
-            };
-        }
-        let encoding = if chardet.confidence >= self.confidence_min {
-            charset2encoding(&charset)
-        } else if let Some(enc) = &self.fallback_encoding {
-            fallbacked = true;
-            enc.as_str()
-        } else {
-            charset2encoding(&charset)
-        };
-        let metadata = Metadata { chardet, encoding: encoding.to_string(), fallbacked, read_bytes };
-        if self.stat {
-            if !self.json {
-                self.print_metadata(&metadata, path, w)?;
-            }
-            return Ok(metadata);
-        }
-        let enc = match encoding_from_whatwg_label(encoding) {
-            Some(e) => e,
-            None => {
-                return Err(DetatError::invalid_input(
-                    InvalidInputErrorKind::NoEncoding(encoding.to_string(), charset.clone()),
-                    format!("no encoding: \"{}\" (charset: \"{}\")", encoding, charset),
-                ));
-            }
-        };
-        let s = match enc.decode(bs.as_slice(), self.decoder_trap) {
-            Ok(s) => s,
-            Err(e) => {
-                return Err(DetatError::decode(e));
-            }
-        };
-        w.write_all(s.as_bytes())?;
-        Ok(metadata)
-    }
+    #[structopt(long, name = "FROM_ENCODING", help = "Decode inputs as <FROM_ENCODING> instead of detecting it")]
+    from_encoding: Option<String>,
 
-    pub fn print_metadata<W: Write>(
-        &self,
-        metadata: &Metadata,
-        path: Option<&Path>,
-        w: &mut W,
-    ) -> Result<(), io::Error> {
-        writeln!(w, "---")?;
-        writeln!(w, "Path: {}", path.and_then(|p| p.to_str()).unwrap_or("-"))?;
-        writeln!(w, "Charset: {}", metadata.chardet.charset)?;
-        writeln!(w, "Confidence: {}", metadata.chardet.confidence)?;
-        writeln!(w, "Language: {}", metadata.chardet.language)?;
-        Ok(())
-    }
+    #[structopt(
+        long,
+        name = "TO_ENCODING",
+        conflicts_with_all = &[
+            "json", "stat", "hex-dump", "stream-output", "LINES", "with-filename", "headers", "FILENAME_STYLE"
+        ],
+        help = "Encode the output in <TO_ENCODING> instead of UTF-8 (without file names, which would be written in \
+                UTF-8)"
+    )]
+    to_encoding: Option<String>,
 
-    pub fn copy_as_json<R: Read, W: Write>(&self, r: &mut R, path: Option<&Path>, w: &mut W) -> DetatResult<Metadata> {
-        let mut content: Vec<u8> = Vec::new();
-        let metadata = self.copy(r, path, &mut content)?;
-        let mut json = {
-            let path = path.and_then(|p| p.to_str()).map(|s| s.to_owned());
-            let content = if self.stat || metadata.chardet.charset.is_empty() {
-                None
-            } else {
-                Some(String::from_utf8(content).unwrap())
-            };
-            let output = Output { metadata: metadata.clone(), path, content };
-            serde_json::to_vec(&output).unwrap()
-        };
-        json.push(b'\n');
-        w.write_all(json.as_slice())?;
-        Ok(metadata)
-    }
+    #[structopt(long, conflicts_with = "FROM_ENCODING", help = "Decode inputs as IBM code page 437 (DOS text art)")]
+    nfo: bool,
 
-    pub fn copy_from_stdin<W: Write>(&self, w: &mut W) -> DetatResult<Metadata> {
-        let stdin = io::stdin();
-        let mut handle = stdin.lock();
-        if self.json {
-            self.copy_as_json(&mut handle, None, w)
-        } else {
-            self.copy(&mut handle, None, w)
-        }
-    }
+    #[structopt(
+        long,
+        name = "FD",
+        conflicts_with = "PATH",
+        help = "Read the input from the file descriptor <FD> (Unix only)"
+    )]
+    fd: Option<i32>,
 
-    pub fn copy_from_file<W: Write>(&self, path: &Path, w: &mut W) -> DetatResult<Metadata> {
-        let mut file = File::open(path)?;
-        if self.json {
-            self.copy_as_json(&mut file, Some(path), w)
-        } else {
-            self.copy(&mut file, Some(path), w)
-        }
-    }
+    #[structopt(
+        long,
+        conflicts_with = "PREFIX",
+        help = "Show results grouped by encoding (JSON Lines are reordered without headings)"
+    )]
+    group_by_encoding: bool,
 
-    pub fn run(&self, path: &Path) -> DetatResult<Metadata> {
-        let stdout = io::stdout();
-        let w = stdout.lock();
-        let mut bw = BufWriter::new(w);
-        let path_str = path.to_str().unwrap();
-        let metadata = if path_str.is_empty() || path_str == "-" {
-            self.copy_from_stdin(&mut bw)
-        } else {
-            self.copy_from_file(path, &mut bw)
-        }?;
-        let confidence = metadata.chardet.confidence;
-        if metadata.read_bytes > 0 && !metadata.fallbacked && confidence < self.confidence_min {
-            return Err(DetatError::invalid_input(
-                InvalidInputErrorKind::LowConfidence(metadata.chardet.charset.clone(), confidence, self.confidence_min),
-                format!(
-                    "confidence: {} < {} (predicted: {})",
-                    confidence, self.confidence_min, metadata.chardet.charset
-                ),
-            ));
-        }
-        Ok(metadata)
-    }
-}
+    #[structopt(
+        long = "conf-threshold",
+        name = "ENCODING=CONFIDENCE",
+        number_of_values = 1,
+        help = "Use <CONFIDENCE> instead of <CONFIDENCE_MIN> if <ENCODING> is detected"
+    )]
+    confidence_thresholds: Vec<ConfidenceThreshold>,
 
-fn main() {
-    env_logger::init();
-    let opt = Opt::from_args();
-    let detat = Detat {
-        confidence_min: opt.confidence_min,
-        fallback_encoding: opt.fallback_encoding,
-        json: opt.json,
-        stat: opt.stat,
-        allow_binary: opt.allow_binary,
-        decoder_trap: opt.decoder_trap.0,
-    };
-    let mut paths = opt.paths;
-    if paths.is_empty() {
-        paths.push(PathBuf::from(""))
-    }
-    let mut error = false;
-    for path in paths.iter() {
-        let result = detat.run(path.as_ref());
-        match result {
-            Ok(_) => {}
-            Err(e) => {
-                error!("{}", e);
-                error = true;
-            }
-        }
-    }
-    if error {
-        exit(1)
+    #[structopt(
+        long,
+        conflicts_with_all = &["PREFIX", "group-by-encoding"],
+        help = "Show all results and a summary in a single JSON document"
+    )]
+    report: bool,
+
+    #[structopt(long, help = "Treat an input consisting only of a BOM as an empty input")]
+    skip_bom_only: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["PREFIX", "group-by-encoding", "report"],
+        help = "Treat inputs as tar archives and process each regular file in them"
+    )]
+    tar: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["PREFIX", "group-by-encoding", "report", "tar"],
+        help = "Treat inputs as zip archives and process each file in them"
+    )]
+    zip: bool,
+
+    #[structopt(long, group = "json-output", help = "Show results in a JSON Lines format with detection details")]
+    debug_json: bool,
+
+    #[structopt(
+        long,
+        name = "MIN_BYTES",
+        default_value = "0",
+        help = "Skip detection and use <ENCODING> (or UTF-8) for inputs shorter than this"
+    )]
+    min_bytes: usize,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["stat", "json", "report", "tar"],
+        help = "Rename inputs whose file names are not UTF-8 by decoding them"
+    )]
+    fix_filenames: bool,
+
+    #[structopt(long, help = "Show what would be done without modifying any file")]
+    dry_run: bool,
+
+    #[structopt(long, help = "Report lines with trailing whitespace and fail if any are found")]
+    check_trailing_whitespace: bool,
+
+    #[structopt(long, help = "Remove trailing whitespace from the output")]
+    fix_trailing_whitespace: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["LINES", "check-trailing-whitespace", "fix-trailing-whitespace", "language"],
+        help = "Write decoded content chunk by chunk instead of decoding it at once"
+    )]
+    stream_output: bool,
+
+    #[structopt(long, help = "Guess the language of the decoded content (requires the `language` feature)")]
+    language: bool,
+
+    #[structopt(long, name = "MAX_ERRORS", help = "Stop processing after <MAX_ERRORS> inputs have failed")]
+    max_errors: Option<usize>,
+
+    #[structopt(
+        long,
+        name = "STYLE",
+        possible_values = &["whatwg", "mime", "iconv", "lower", "upper"],
+        help = "Show encoding names in this style"
+    )]
+    name_style: Option<NameStyle>,
+
+    #[structopt(long, help = "Use the `encoding:` declared in the YAML front matter of inputs")]
+    frontmatter: bool,
+
+    #[structopt(
+        long,
+        name = "PASSES",
+        default_value = "1",
+        help = "Detect <PASSES> (1..16) times with different chunk sizes and fall back (or fail) unless all agree"
+    )]
+    passes: usize,
+
+    #[structopt(short, long, help = "Process files in directories recursively")]
+    recursive: bool,
+
+    #[structopt(
+        long = "exclude",
+        name = "GLOB",
+        number_of_values = 1,
+        requires = "recursive",
+        help = "Skip paths matching <GLOB> while processing directories recursively"
+    )]
+    excludes: Vec<walk::Glob>,
+
+    #[structopt(
+        long,
+        name = "ORDER",
+        possible_values = &["byte", "locale", "natural"],
+        requires = "recursive",
+        help = "Process the files in each directory in byte order, in the collation order of the locale or in \
+                natural order, where `file2` comes before `file10` [default: byte]"
+    )]
+    sort: Option<walk::SortOrder>,
+
+    #[structopt(long, help = "Sort JSON object keys lexicographically for byte-stable output")]
+    json_ordered: bool,
+
+    #[structopt(long, help = "Detect UTF-32 (LE/BE) inputs by their BOM or zero byte pattern and decode them")]
+    utf32: bool,
+
+    #[structopt(long, help = "Detect UTF-16 (LE/BE) inputs without a BOM by their zero byte pattern and decode them")]
+    utf16: bool,
+
+    #[structopt(
+        long,
+        name = "LINE_ENDING",
+        possible_values = &["lf", "crlf", "cr", "auto"],
+        conflicts_with = "stream-output",
+        help = "Convert line endings to <LINE_ENDING>, or to the most frequent one in each input with `auto`"
+    )]
+    line_ending: Option<LineEnding>,
+
+    #[structopt(long, conflicts_with = "forbid-bom", help = "Fail on inputs without a byte order mark")]
+    require_bom: bool,
+
+    #[structopt(long, help = "Fail on inputs with a byte order mark")]
+    forbid_bom: bool,
+
+    #[structopt(
+        long,
+        help = "Use the encoding declared by an Emacs, Vim or Python coding comment in the first two lines of inputs"
+    )]
+    respect_coding_comment: bool,
+
+    #[structopt(long, help = "Write the metadata of each input file to <PATH>.meta.json (not for stdin)")]
+    emit_meta_sidecar: bool,
+
+    #[structopt(
+        long,
+        help = "Print the lines which differ between the detector's guess and the fallback decoding to stderr \
+                (decodes fallbacked inputs twice)"
+    )]
+    show_fallback_diff: bool,
+
+    #[structopt(
+        long,
+        help = "Show statistics detected from the first <SAMPLE_BYTES> of each input without reading the rest; \
+                much faster on large files, but misses characters which only appear later"
+    )]
+    detect_only: bool,
+
+    #[structopt(
+        long,
+        name = "SAMPLE_BYTES",
+        help = "Read at most <SAMPLE_BYTES> of each input with --detect-only, or feed the detector that many bytes \
+                with --sample-strategy [default: 4096]"
+    )]
+    sample_bytes: Option<usize>,
+
+    #[structopt(
+        long,
+        name = "STRATEGY",
+        possible_values = &["head", "tail", "spread"],
+        help = "Feed the detector the first, the last, or the beginning, middle and end <SAMPLE_BYTES> of each \
+                input instead of all of it, while still decoding all of it"
+    )]
+    sample_strategy: Option<SampleStrategy>,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["json", "stat", "SIZE", "detect-only"],
+        help = "Output an xxd-style hex dump of the raw input headed by the detected encoding"
+    )]
+    hex_dump: bool,
+
+    #[structopt(long, name = "SECONDS", help = "Fail inputs which take longer than <SECONDS> to read")]
+    timeout: Option<f64>,
+
+    #[structopt(
+        long,
+        requires = "SECONDS",
+        help = "Process what has been read of inputs which time out, marking them as partial"
+    )]
+    partial_on_timeout: bool,
+
+    #[structopt(
+        long,
+        name = "ENCODINGS",
+        use_delimiter = true,
+        help = "When the detector is not confident, use the first of these comma-separated encodings which decodes \
+                the input without errors before falling back"
+    )]
+    encoding_priority: Vec<String>,
+
+    #[structopt(
+        long,
+        group = "json-output",
+        help = "Show results as pretty-printed JSON records each prefixed by <SEPARATOR> (RFC 7464 JSON text \
+                sequences by default)"
+    )]
+    json_seq: bool,
+
+    #[structopt(
+        long,
+        name = "SEPARATOR",
+        default_value = "\u{1e}",
+        hide_default_value = true,
+        help = "The record separator of --json-seq [default: RS (0x1E)]"
+    )]
+    record_separator: String,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["stat", "PREFIX", "report", "group-by-encoding", "tar", "fix-filenames"],
+        help = "Only report whether each input is valid UTF-8 (and where it is not) without running the detector"
+    )]
+    is_valid_utf8: bool,
+
+    #[structopt(
+        long,
+        name = "RATIO",
+        help = "Also treat inputs as binary if more than <RATIO> (0..1) of their leading bytes are NUL or other C0 \
+                control codes except tab, LF and CR (off by default)"
+    )]
+    binary_threshold: Option<f32>,
+
+    #[structopt(
+        long,
+        name = "BINARY_SAMPLE_BYTES",
+        default_value = "8192",
+        help = "Examine the first <BINARY_SAMPLE_BYTES> of inputs for --binary-threshold"
+    )]
+    binary_sample_bytes: usize,
+
+    #[structopt(
+        long,
+        conflicts_with = "stream-output",
+        help = "Add a line ending to non-empty content which does not end with one (in the --line-ending style if \
+                given)"
+    )]
+    posix_newline: bool,
+
+    #[structopt(
+        long,
+        conflicts_with = "fix-filenames",
+        help = "Swap each pair of adjacent bytes of inputs before detecting and decoding them (e.g. for UTF-16 with \
+                the wrong byte order and no BOM); odd-length inputs are errors"
+    )]
+    swap_bytes: bool,
+
+    #[structopt(
+        long,
+        conflicts_with = "stream-output",
+        help = "Guess the CSV field delimiter (comma, tab, semicolon or pipe) of inputs and show it in the metadata"
+    )]
+    csv_sniff: bool,
+
+    #[structopt(
+        long,
+        name = "FAILURES_FILE",
+        parse(from_os_str),
+        help = "Append the paths of failed inputs to <FAILURES_FILE>"
+    )]
+    failures_to: Option<PathBuf>,
+
+    #[structopt(long, requires = "FAILURES_FILE", help = "Separate the paths in --failures-to with NUL instead of LF")]
+    print0: bool,
+
+    #[structopt(
+        long,
+        requires = "FAILURES_FILE",
+        help = "Add a tab and the class of the failure to each path in --failures-to"
+    )]
+    failure_reasons: bool,
+
+    #[structopt(
+        long,
+        name = "MAX_CHARS",
+        conflicts_with = "stream-output",
+        help = "Stop decoding inputs after <MAX_CHARS> characters, marking them as truncated"
+    )]
+    max_chars: Option<usize>,
+
+    #[structopt(
+        long,
+        name = "SUMMARY_FILE",
+        parse(from_os_str),
+        help = "Write the statistics of the run (encodings, totals, errors, elapsed time) as JSON to <SUMMARY_FILE>"
+    )]
+    summary_json: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Ask on the terminal which encoding to use for inputs detected with low confidence, showing how the \
+                candidates decode them (only when stdin is a terminal)"
+    )]
+    interactive: bool,
+
+    #[structopt(
+        short = "H",
+        long,
+        help = "Show the file name with the content even for a single input (`path: ` before each line unless \
+                --filename-style says otherwise)"
+    )]
+    with_filename: bool,
+
+    #[structopt(
+        long,
+        alias = "no-headers",
+        conflicts_with_all = &["with-filename", "headers"],
+        help = "Never show file names with the content"
+    )]
+    no_filename: bool,
+
+    #[structopt(
+        long,
+        name = "FILENAME_STYLE",
+        possible_values = &["prefix", "banner", "header"],
+        help = "Show file names before each line, as tail-style `==> path <==` banners or as `===== path (encoding) \
+                =====` headers [default: header for multiple inputs on a terminal, none otherwise, prefix with \
+                -H]"
+    )]
+    filename_style: Option<FilenameStyle>,
+
+    #[structopt(
+        long,
+        conflicts_with = "FILENAME_STYLE",
+        help = "Head each input with `===== path (encoding) =====` even for a single input or when not writing to a \
+                terminal (without the encoding with --stream-output)"
+    )]
+    headers: bool,
+
+    #[structopt(
+        long,
+        help = "Process each file once even if it is given more than once, comparing canonicalized paths"
+    )]
+    unique_paths: bool,
+
+    #[structopt(
+        long,
+        name = "TRANSFORMS",
+        use_delimiter = true,
+        possible_values = &["trailing-whitespace", "line-ending", "posix-newline"],
+        help = "Apply the transforms enabled by --fix-trailing-whitespace, --line-ending and --posix-newline in this \
+                order, which must name each of them [default: trailing-whitespace,line-ending,posix-newline]"
+    )]
+    pipeline: Vec<Transform>,
+
+    #[structopt(
+        long,
+        name = "CHARS",
+        help = "Include the first <CHARS> decoded characters of each input as `sample` in JSON output, even \
+                without the content"
+    )]
+    json_sample: Option<usize>,
+
+    #[structopt(
+        long,
+        help = "Report inputs which are all ASCII as UTF-8 with full confidence (ASCII is valid in both, so the \
+                content is the same)"
+    )]
+    ascii_is_utf8: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["PATH", "TO_ENCODING"],
+        help = "Read JSON Lines written with --json from stdin and write them back with the content transforms \
+                (e.g. --line-ending) applied to their content"
+    )]
+    from_jsonl: bool,
+
+    #[structopt(
+        long,
+        name = "CONFIDENT_RATIO",
+        help = "Fail if less than <CONFIDENT_RATIO> of the inputs are detected confidently (without falling back) after \
+                processing all of them (counting archive entries), or if none was processed"
+    )]
+    min_confident_ratio: Option<f64>,
+
+    #[structopt(
+        long,
+        help = "Report whether the decoded content looks like UTF-8 text that was once decoded with a single-byte \
+                encoding (e.g. `Ã©` for `é`), suggesting UTF-8 as the original encoding"
+    )]
+    suspect_mojibake: bool,
+
+    #[structopt(long, help = "Show NUL characters in JSON content as `^@` instead of `\\u0000`")]
+    escape_nul: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["json", "report", "tar", "zip", "PREFIX", "group-by-encoding", "is-valid-utf8"],
+        help = "Only print `true` or `false` for each input, whether its encoding was detected confidently, and \
+                exit successfully either way"
+    )]
+    confident_only: bool,
+
+    #[structopt(
+        long,
+        requires = "SIZE",
+        help = "Merge consecutive windows of --window-detect with the same encoding, reporting how many each spans"
+    )]
+    merge_segments: bool,
+
+    #[structopt(
+        long,
+        name = "MAP_FILE",
+        parse(from_os_str),
+        help = "Rename encodings and charsets in the output by the JSON object in <MAP_FILE> (e.g. \
+                `{\"windows-1252\": \"WE8MSWIN1252\"}`), after --name-style"
+    )]
+    charset_map: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        conflicts_with = "PATH",
+        help = "Read stdin in small chunks until the detector is confident (0.9 or --confidence-min if higher), \
+                print the encoding, confidence and bytes read, and leave the rest of stdin unread; fails if the end \
+                is reached first"
+    )]
+    probe: bool,
+
+    #[structopt(
+        long,
+        name = "GUESSES",
+        use_delimiter = true,
+        requires = "ENCODING",
+        help = "Only fall back when the unconfident guess is one of these encodings, keeping other guesses"
+    )]
+    fallback_for: Vec<String>,
+
+    #[structopt(
+        long,
+        help = "Count the letters, digits, punctuation, whitespace, control and other characters of the decoded \
+                content as `char_stats`"
+    )]
+    char_stats: bool,
+
+    #[structopt(
+        long,
+        name = "HEADER_BYTES",
+        default_value = "0",
+        help = "Skip the first <HEADER_BYTES> of each input (e.g. an ASCII header) when feeding the detector, still \
+                decoding all of it; inputs starting with a BOM are detected by it as usual"
+    )]
+    skip_header_bytes: usize,
+
+    #[structopt(
+        long,
+        help = "Fail if any decoded content has U+FFFD REPLACEMENT CHARACTERs, after writing it (not checked with \
+                --stream-output)"
+    )]
+    fail_on_replacement: bool,
+
+    #[structopt(
+        long,
+        requires = "TO_ENCODING",
+        help = "Keep the BOM of inputs which have one when converting to a UTF encoding with --to-encoding"
+    )]
+    retain_bom: bool,
+
+    #[structopt(
+        long,
+        name = "REFERENCE",
+        parse(from_os_str),
+        conflicts_with_all = &[
+            "json", "report", "tar", "zip", "PREFIX", "group-by-encoding", "is-valid-utf8", "confident-only",
+            "TO_ENCODING", "hex-dump", "SIZE", "LINES"
+        ],
+        help = "Compare the decoded content of each input with the decoded content of <REFERENCE>, printing the lines \
+                which differ and exiting with an error if any input differs"
+    )]
+    diff_against: Option<PathBuf>,
+
+    #[structopt(long, hidden = true, help = "Print the version, options and environment for a bug report and exit")]
+    env_dump: bool,
+
+    #[structopt(
+        long,
+        requires = "json-output",
+        help = "Omit empty inputs from the JSON output (--json, --json-seq or --debug-json)"
+    )]
+    skip_empty_json: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["group-by-encoding", "PREFIX", "report", "hex-dump", "SIZE"],
+        help = "Output only the content, overriding --stat, --json, --debug-json, --preview and the file names (e.g. \
+                --headers); cannot be combined with modes which write other output (e.g. --report)"
+    )]
+    content_only: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChardetResult {
+    charset: String,
+    confidence: f32,
+    language: String,
+}
+
+impl ChardetResult {
+    pub fn new(charset: String, confidence: f32, language: String) -> ChardetResult {
+        ChardetResult { charset, confidence, language }
+    }
+
+    pub fn from_tuple((charset, confidence, language): (String, f32, String)) -> ChardetResult {
+        Self::new(charset, confidence, language)
+    }
+}
+
+/// Fields missing from the JSON written by earlier versions (e.g. read by `--from-jsonl`) take their defaults, as
+/// do flags which are left out when false.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    chardet: ChardetResult,
+    encoding: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    is_empty: bool,
+    fallbacked: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    prioritized: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    chosen: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    fallback_declined: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    forced: bool,
+    /// What forced the encoding (e.g. `--from-encoding` or a coding comment), shown by `--debug-json` only.
+    #[serde(skip)]
+    forced_encoding: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    bom_only: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    bom_detected: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    binary: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    too_short: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    builtin_utf32: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    non_whatwg: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    utf16_endianness: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    utf16_from_bom: bool,
+    replacements: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    bytes_swapped: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    partial: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    trailing_whitespace_lines: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agreement: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line_ending: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    final_newline: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    csv_delimiter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    char_stats: Option<CharStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mojibake_suspected: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggested_encoding: Option<String>,
+    read_bytes: usize,
+}
+
+/// Writes `prefix` at the start of each line written through it.
+struct PrefixWriter<W: Write> {
+    inner: W,
+    prefix: String,
+    line_start: bool,
+}
+
+impl<W: Write> Write for PrefixWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            if self.line_start {
+                self.inner.write_all(self.prefix.as_bytes())?;
+            }
+            self.inner.write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+    path: Option<String>,
+    metadata: Metadata,
+    content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name_encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sample: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    debug: Option<DebugInfo>,
+}
+
+/// Detection details emitted by `--debug-json`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DebugInfo {
+    sample_bytes: usize,
+    bom: Option<String>,
+    confidence_min: f32,
+    fallback_encoding: Option<String>,
+    forced_encoding: Option<String>,
+    guess: Option<String>,
+    encoding: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Summary {
+    files: usize,
+    errors: usize,
+    excluded: usize,
+    #[serde(default)]
+    duplicates: usize,
+    #[serde(default)]
+    confident: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confident_ratio: Option<f64>,
+    read_bytes: usize,
+    #[serde(default)]
+    replacements: usize,
+    encodings: BTreeMap<String, usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    error_reasons: BTreeMap<String, usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    elapsed_secs: Option<f64>,
+}
+
+impl Metadata {
+    /// Returns true if the encoding was detected, not taken from a fallback or asked for.
+    pub fn is_confident(&self) -> bool {
+        self.encoding.is_some()
+            && !self.binary
+            && !self.too_short
+            && !self.fallbacked
+            && !self.prioritized
+            && !self.chosen
+            && !self.fallback_declined
+    }
+}
+
+impl Summary {
+    pub fn add(&mut self, metadata: &Metadata) {
+        self.files += 1;
+        if metadata.is_confident() {
+            self.confident += 1;
+        }
+        self.read_bytes += metadata.read_bytes;
+        self.replacements += metadata.replacements;
+        if let Some(encoding) = &metadata.encoding {
+            *self.encodings.entry(encoding.clone()).or_insert(0) += 1;
+        }
+    }
+
+    pub fn add_error(&mut self, e: &DetatError) {
+        *self.error_reasons.entry(e.reason().to_string()).or_insert(0) += 1;
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Report {
+    files: Vec<Output>,
+    summary: Summary,
+}
+
+/// The numbers of characters by category in decoded content, for `--char-stats`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CharStats {
+    letters: usize,
+    digits: usize,
+    punctuation: usize,
+    whitespace: usize,
+    control: usize,
+    other: usize,
+}
+
+impl CharStats {
+    pub fn count(s: &str) -> CharStats {
+        let mut stats = CharStats::default();
+        for c in s.chars() {
+            // Whitespace first, as line endings and tabs are control characters too.
+            if c.is_whitespace() {
+                stats.whitespace += 1;
+            } else if c.is_control() {
+                stats.control += 1;
+            } else if c.is_alphabetic() {
+                stats.letters += 1;
+            } else if c.is_numeric() {
+                stats.digits += 1;
+            } else if is_punctuation(c) {
+                stats.punctuation += 1;
+            } else {
+                stats.other += 1;
+            }
+        }
+        stats
+    }
+}
+
+/// Returns true for ASCII punctuation and the common punctuation of Latin-1, General Punctuation, CJK and fullwidth
+/// forms, leaving symbols (e.g. currency and math) out.
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() && !"$+<=>^`|~".contains(c)
+        || matches!(
+            c,
+            '\u{A1}' | '\u{A7}' | '\u{AB}' | '\u{B6}' | '\u{B7}' | '\u{BB}' | '\u{BF}'
+                | '\u{2010}'..='\u{2027}'
+                | '\u{2030}'..='\u{2043}'
+                | '\u{2045}'..='\u{2051}'
+                | '\u{2053}'..='\u{205E}'
+                | '\u{3001}'..='\u{3003}'
+                | '\u{3008}'..='\u{3011}'
+                | '\u{3014}'..='\u{301F}'
+                | '\u{FF01}'..='\u{FF03}'
+                | '\u{FF05}'..='\u{FF0A}'
+                | '\u{FF0C}'..='\u{FF0F}'
+                | '\u{FF1A}'
+                | '\u{FF1B}'
+                | '\u{FF1F}'
+                | '\u{FF20}'
+                | '\u{FF3B}'..='\u{FF3D}'
+                | '\u{FF3F}'
+                | '\u{FF5B}'
+                | '\u{FF5D}'
+                | '\u{FF5F}'..='\u{FF65}'
+        )
+}
+
+/// A result of `--probe`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProbeResult {
+    chardet: ChardetResult,
+    encoding: Option<String>,
+    confident: bool,
+    read_bytes: usize,
+}
+
+/// A result of `--env-dump`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EnvDump {
+    version: String,
+    dependencies: BTreeMap<String, String>,
+    features: Vec<String>,
+    options: String,
+    env: BTreeMap<String, Option<String>>,
+    os: String,
+    arch: String,
+}
+
+impl EnvDump {
+    pub fn new(opt: &Opt) -> EnvDump {
+        let features = [
+            ("language", cfg!(feature = "language")),
+            ("legacy-encodings", cfg!(feature = "legacy-encodings")),
+            ("tar", cfg!(feature = "tar")),
+            ("zip", cfg!(feature = "zip")),
+        ];
+        let vars = ["LANG", "LC_ALL", "LC_CTYPE", "RUST_LOG"];
+        EnvDump {
+            version: option_env!("LONG_VERSION").unwrap_or(env!("CARGO_PKG_VERSION")).to_string(),
+            // As locked in Cargo.lock by build.rs.
+            dependencies: env!("DETAT_DEPENDENCIES")
+                .split(',')
+                .filter_map(|dependency| dependency.split_once('='))
+                .map(|(name, version)| (name.to_string(), version.to_string()))
+                .collect(),
+            features: features.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()).collect(),
+            options: format!("{:?}", opt),
+            env: vars.iter().map(|k| (k.to_string(), env::var(k).ok())).collect(),
+            os: env::consts::OS.to_string(),
+            arch: env::consts::ARCH.to_string(),
+        }
+    }
+
+    pub fn print<W: Write>(&self, json: bool, w: &mut W) -> io::Result<()> {
+        if json {
+            serde_json::to_writer(&mut *w, self)?;
+            return writeln!(w);
+        }
+        writeln!(w, "detat {}", self.version)?;
+        for (name, version) in &self.dependencies {
+            writeln!(w, "{} {}", name, version)?;
+        }
+        writeln!(w, "features: {}", self.features.join(","))?;
+        writeln!(w, "platform: {} {}", self.os, self.arch)?;
+        for (k, v) in &self.env {
+            writeln!(w, "{}={}", k, v.as_deref().unwrap_or(""))?;
+        }
+        writeln!(w, "options: {}", self.options)
+    }
+}
+
+/// A result of `--is-valid-utf8`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Utf8Validity {
+    path: Option<String>,
+    valid: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error_offset: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WindowResult {
+    offset: usize,
+    encoding: String,
+    confidence: f32,
+    has_confidence: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    windows: Option<usize>,
+}
+
+pub struct Detat {
+    confidence_min: f32,
+    fallback_encoding: Option<String>,
+    json: bool,
+    stat: bool,
+    allow_binary: bool,
+    decoder_trap: DecoderTrap,
+    window_size: Option<usize>,
+    detector: RefCell<UniversalDetector>,
+    json_numbers_as_strings: bool,
+    preview: Option<usize>,
+    path_encodings: Vec<PathEncoding>,
+    from_encoding: Option<String>,
+    confidence_thresholds: Vec<ConfidenceThreshold>,
+    skip_bom_only: bool,
+    debug_json: bool,
+    min_bytes: usize,
+    dry_run: bool,
+    check_trailing_whitespace: bool,
+    stream_output: bool,
+    language: bool,
+    name_style: Option<NameStyle>,
+    frontmatter: bool,
+    passes: usize,
+    json_ordered: bool,
+    utf32: bool,
+    utf16: bool,
+    line_ending: Option<LineEnding>,
+    require_bom: bool,
+    forbid_bom: bool,
+    coding_comment: Option<regex::bytes::Regex>,
+    emit_meta_sidecar: bool,
+    show_fallback_diff: bool,
+    read_limit: Option<usize>,
+    sample_strategy: Option<SampleStrategy>,
+    sample_size: usize,
+    transforms: Vec<Box<dyn Fn(String) -> String>>,
+    timeout: Option<Duration>,
+    partial_on_timeout: bool,
+    encoding_priority: Vec<String>,
+    hex_dump: bool,
+    to_encoding: Option<EncodingRef>,
+    record_separator: Option<String>,
+    binary_threshold: Option<f32>,
+    binary_sample_bytes: usize,
+    swap_bytes: bool,
+    csv_sniff: bool,
+    max_chars: Option<usize>,
+    interactive: bool,
+    pipeline: Vec<Transform>,
+    json_sample: Option<usize>,
+    ascii_is_utf8: bool,
+    suspect_mojibake: bool,
+    escape_nul: bool,
+    merge_segments: bool,
+    charset_map: BTreeMap<String, String>,
+    fallback_for: Vec<&'static str>,
+    char_stats: bool,
+    skip_header_bytes: usize,
+    fail_on_replacement: bool,
+    retain_bom: bool,
+    skip_empty_json: bool,
+    filename_style: Option<FilenameStyle>,
+    banner_written: Cell<bool>,
+}
+
+impl Default for Detat {
+    /// Returns a `Detat` with the defaults of the command line options.
+    fn default() -> Detat {
+        Detat {
+            confidence_min: 0.0,
+            fallback_encoding: None,
+            json: false,
+            stat: false,
+            allow_binary: false,
+            decoder_trap: DecoderTrap::Strict,
+            window_size: None,
+            detector: RefCell::new(UniversalDetector::new()),
+            json_numbers_as_strings: false,
+            preview: None,
+            path_encodings: Vec::new(),
+            from_encoding: None,
+            confidence_thresholds: Vec::new(),
+            skip_bom_only: false,
+            debug_json: false,
+            min_bytes: 0,
+            dry_run: false,
+            check_trailing_whitespace: false,
+            stream_output: false,
+            language: false,
+            name_style: None,
+            frontmatter: false,
+            passes: 1,
+            json_ordered: false,
+            utf32: false,
+            utf16: false,
+            line_ending: None,
+            require_bom: false,
+            forbid_bom: false,
+            coding_comment: None,
+            emit_meta_sidecar: false,
+            show_fallback_diff: false,
+            read_limit: None,
+            sample_strategy: None,
+            sample_size: 4096,
+            transforms: Vec::new(),
+            timeout: None,
+            partial_on_timeout: false,
+            encoding_priority: Vec::new(),
+            hex_dump: false,
+            to_encoding: None,
+            record_separator: None,
+            binary_threshold: None,
+            binary_sample_bytes: 8192,
+            swap_bytes: false,
+            csv_sniff: false,
+            max_chars: None,
+            interactive: false,
+            pipeline: Vec::new(),
+            json_sample: None,
+            ascii_is_utf8: false,
+            suspect_mojibake: false,
+            escape_nul: false,
+            merge_segments: false,
+            charset_map: BTreeMap::new(),
+            fallback_for: Vec::new(),
+            char_stats: false,
+            skip_header_bytes: 0,
+            fail_on_replacement: false,
+            retain_bom: false,
+            skip_empty_json: false,
+            filename_style: None,
+            banner_written: Cell::new(false),
+        }
+    }
+}
+
+impl Detat {
+    /// Registers `transform` to be applied to the decoded content before output, after the transforms registered
+    /// before it and the built-in ones (e.g. `--fix-trailing-whitespace`). Not applied with `--stream-output`.
+    pub fn add_transform(&mut self, transform: Box<dyn Fn(String) -> String>) {
+        self.transforms.push(transform);
+    }
+
+    /// Detects the charset of `bs`, reusing the detector (and its probers) across inputs.
+    pub fn detect(&self, bs: &[u8]) -> ChardetResult {
+        let mut detector = self.detector.borrow_mut();
+        detector.reset();
+        detector.feed(bs);
+        ChardetResult::from_tuple(detector.close())
+    }
+
+    /// Detects `bs` fed in chunks of a different size on each pass, returning the most frequent result and the
+    /// ratio of passes which agree with it.
+    pub fn detect_passes(&self, bs: &[u8]) -> (ChardetResult, f32) {
+        let mut results: Vec<(ChardetResult, usize)> = Vec::new();
+        for pass in 0..self.passes {
+            // 64 bytes on the second pass, doubling on each pass after it.
+            let chunk_size =
+                if pass == 0 { bs.len() } else { 64usize.checked_shl(pass as u32 - 1).unwrap_or(usize::MAX) };
+            let mut detector = self.detector.borrow_mut();
+            detector.reset();
+            for chunk in bs.chunks(chunk_size.clamp(1, bs.len().max(1))) {
+                detector.feed(chunk);
+            }
+            let chardet = ChardetResult::from_tuple(detector.close());
+            match results.iter_mut().find(|(r, _)| r.charset == chardet.charset) {
+                Some((_, count)) => *count += 1,
+                None => results.push((chardet, 1)),
+            }
+        }
+        let (chardet, count) = results.into_iter().rev().max_by_key(|&(_, count)| count).unwrap_or_default();
+        (chardet, count as f32 / self.passes as f32)
+    }
+
+    /// Reads `r` to the end, or only its first `--sample-bytes` with `--detect-only`.
+    fn read_input<R: Read>(&self, r: &mut R) -> io::Result<Vec<u8>> {
+        let mut bs = Vec::new();
+        match self.read_limit {
+            // One more byte tells whether the input goes on after the limit.
+            Some(limit) => r.take(limit as u64 + 1).read_to_end(&mut bs)?,
+            None => r.read_to_end(&mut bs)?,
+        };
+        self.cut_to_limit(&mut bs);
+        Ok(bs)
+    }
+
+    /// Cuts `bs` read up to one byte past `--sample-bytes` with `--detect-only` down to it, dropping a UTF-8
+    /// sequence split by the cut, which would otherwise keep the sample from being detected as UTF-8.
+    fn cut_to_limit(&self, bs: &mut Vec<u8>) {
+        if let Some(limit) = self.read_limit.filter(|&limit| bs.len() > limit) {
+            bs.truncate(limit);
+            let len = trim_partial_utf8(bs).len();
+            bs.truncate(len);
+        }
+    }
+
+    /// Reads `r` like `read_input`, giving up after `--timeout`. Returns the bytes read so far and true instead of
+    /// an error on timeout with `--partial-on-timeout`.
+    fn read_with_timeout<R: Read + Send + 'static>(
+        &self,
+        mut r: R,
+        path: Option<&Path>,
+    ) -> DetatResult<(Vec<u8>, bool)> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return Ok((self.read_input(&mut r)?, false)),
+        };
+        let limit = self.read_limit.map_or(usize::MAX, |limit| limit + 1);
+        let (tx, rx) = mpsc::channel();
+        // The reader is left behind if it hangs; it does not keep detat from exiting.
+        thread::spawn(move || {
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                let result = match r.read(&mut buf) {
+                    Ok(n) => Ok(buf[..n].to_vec()),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => Err(e),
+                };
+                let done = !matches!(result, Ok(ref chunk) if !chunk.is_empty());
+                if tx.send(result).is_err() || done {
+                    return;
+                }
+            }
+        });
+        let deadline = Instant::now() + timeout;
+        let mut bs = Vec::new();
+        while bs.len() < limit {
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Ok(chunk)) if chunk.is_empty() => break,
+                Ok(Ok(chunk)) => bs.extend_from_slice(&chunk),
+                Ok(Err(e)) => return Err(e.into()),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    let path = path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy());
+                    if !self.partial_on_timeout {
+                        let message = format!("{}: timed out after {:?}", path, timeout);
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, message).into());
+                    }
+                    warn!("{}: timed out after {:?}, using the {} bytes read so far", path, timeout, bs.len());
+                    return Ok((bs, true));
+                }
+            }
+        }
+        self.cut_to_limit(&mut bs);
+        Ok((bs, false))
+    }
+
+    pub fn copy<R: Read, W: Write>(&self, r: &mut R, path: Option<&Path>, w: &mut W) -> DetatResult<Metadata> {
+        let bs = self.read_input(r)?;
+        self.copy_bytes(&bs, path, w)
+    }
+
+    /// Reads at most `--sample-bytes` of `r` and detects their charset without decoding anything, returning the
+    /// guess, whether it is confident (at least `--confidence-min` or its `--conf-threshold`) and the bytes read.
+    pub fn detect_reader<R: Read>(&self, r: &mut R) -> DetatResult<(ChardetResult, bool, usize)> {
+        let mut bs = Vec::new();
+        let read_bytes = r.take(self.sample_size as u64).read_to_end(&mut bs)?;
+        // A character split by the end of the sample would keep UTF-8 from being detected.
+        let bs = if read_bytes == self.sample_size { trim_partial_utf8(&bs) } else { &bs };
+        let chardet = self.detect(&self.sample(bs));
+        let confident = !chardet.charset.is_empty() && chardet.confidence >= self.confidence_min_for(&chardet.charset);
+        Ok((chardet, confident, read_bytes))
+    }
+
+    /// Returns the part of `bs` the detector is fed according to `--skip-header-bytes` and `--sample-strategy`.
+    pub fn sample<'a>(&self, bs: &'a [u8]) -> Cow<'a, [u8]> {
+        // The detector recognizes BOMs only at the beginning of what it is fed, so those are never skipped.
+        let bs = if bom_encoding(bs).is_none() { &bs[self.skip_header_bytes.min(bs.len())..] } else { bs };
+        let size = self.sample_size.min(bs.len());
+        // The cuts are moved to UTF-8 character boundaries, so that they do not make UTF-8 look invalid.
+        match self.sample_strategy {
+            None => Cow::Borrowed(bs),
+            Some(SampleStrategy::Head) => Cow::Borrowed(trim_partial_utf8(&bs[..size])),
+            Some(SampleStrategy::Tail) => Cow::Borrowed(skip_utf8_continuation(&bs[bs.len() - size..])),
+            Some(SampleStrategy::Spread) => {
+                let part = size / 3;
+                let middle = (bs.len() - part) / 2;
+                let mut sample = Vec::with_capacity(size);
+                sample.extend_from_slice(trim_partial_utf8(&bs[..part]));
+                sample.extend_from_slice(trim_partial_utf8(skip_utf8_continuation(&bs[middle..middle + part])));
+                sample.extend_from_slice(skip_utf8_continuation(&bs[bs.len() - (size - 2 * part)..]));
+                Cow::Owned(sample)
+            }
+        }
+    }
+
+    pub fn copy_bytes<W: Write>(&self, bs: &[u8], path: Option<&Path>, w: &mut W) -> DetatResult<Metadata> {
+        let bs = self.swap_input(bs)?;
+        self.copy_bytes_with_encoding(&bs, path, w).map(|(metadata, _)| metadata)
+    }
+
+    /// Returns `bs` with each pair of adjacent bytes swapped with `--swap-bytes`, as it is detected and decoded.
+    fn swap_input<'a>(&self, bs: &'a [u8]) -> DetatResult<Cow<'a, [u8]>> {
+        if self.swap_bytes {
+            swap_byte_pairs(bs).map(Cow::Owned)
+        } else {
+            Ok(Cow::Borrowed(bs))
+        }
+    }
+
+    /// Does what `copy_bytes` does for `bs` already passed through `swap_input`, also returning the encoding
+    /// `metadata.encoding` names, which may have been renamed by `--name-style` or `--charset-map` beyond
+    /// recognition.
+    fn copy_bytes_with_encoding<W: Write>(
+        &self,
+        bs: &[u8],
+        path: Option<&Path>,
+        w: &mut W,
+    ) -> DetatResult<(Metadata, Option<EncodingRef>)> {
+        let bytes_swapped = self.swap_bytes;
+        let read_bytes = bs.len();
+        let too_short = read_bytes < self.min_bytes;
+        let (mut chardet, agreement) = if too_short {
+            (ChardetResult::default(), None)
+        } else if self.passes > 1 {
+            let (chardet, agreement) = self.detect_passes(&self.sample(bs));
+            (chardet, Some(agreement))
+        } else if self.read_limit.is_some() {
+            // With --detect-only, `bs` is no more than the sample `detect_reader` reads; what forces the encoding or
+            // a BOM is taken into account below, as for full inputs.
+            (self.detect_reader(&mut &*bs)?.0, None)
+        } else {
+            (self.detect(&self.sample(bs)), None)
+        };
+        info!("predicted: {}, confidence: {}, language: {}", chardet.charset, chardet.confidence, chardet.language);
+        let bom_only = is_bom_only(bs);
+        if bs.is_empty() || (bom_only && self.skip_bom_only) {
+            let metadata = Metadata {
+                is_empty: true,
+                bom_only,
+                bom_detected: bom_only,
+                bytes_swapped,
+                read_bytes,
+                ..Metadata::default()
+            };
+            if self.stat && !self.json {
+                self.print_metadata(&metadata, path, w)?;
+            }
+            return Ok((metadata, None));
+        }
+        let mut fallbacked = false;
+        let mut prioritized = false;
+        let mut chosen = false;
+        let mut fallback_declined = false;
+        let charset = chardet.charset.clone();
+        let forced = path
+            .and_then(|p| self.path_encoding(p))
+            .or(self.from_encoding.as_deref())
+            .or_else(|| if self.frontmatter { frontmatter_encoding(bs) } else { None })
+            .or_else(|| self.coding_comment.as_ref().and_then(|re| coding_comment_encoding(re, bs)));
+        // chardet neither guesses UTF-32 nor treats it as text, so it is checked beforehand.
+        let utf32 = if self.utf32 && forced.is_none() { utf32::detect(bs) } else { None };
+        let utf16 = if self.utf16 && forced.is_none() && utf32.is_none() { utf16_detect(bs) } else { None };
+        let binary = charset.is_empty() || self.looks_binary(bs);
+        if binary && forced.is_none() && utf32.is_none() && utf16.is_none() && !too_short {
+            // JSON has no representation for raw bytes, so binary inputs are reported with `null` content there.
+            return if self.allow_binary || self.json {
+                let metadata = Metadata { chardet, binary: true, bytes_swapped, read_bytes, ..Metadata::default() };
+                if self.stat {
+                    if !self.json {
+                        self.print_metadata(&metadata, path, w)?;
+                    }
+                } else if !self.json {
+                    w.write_all(bs)?;
+                }
+                Ok((metadata, None))
+            } else {
+                Err(DetatError::invalid_input(InvalidInputErrorKind::IsBinary, "Input is binary".to_string()))
+            };
+        }
+        let encoding = if let Some(enc) = forced.or(utf32).or(utf16) {
+            enc
+        } else if self.ascii_is_utf8 && bs.is_ascii() {
+            // ASCII decodes the same in UTF-8 as in any ASCII-compatible guess, so only the report changes. The whole
+            // input is checked, as a sample (e.g. with --skip-header-bytes) may miss the bytes UTF-8 cannot decode.
+            chardet.confidence = 1.0;
+            "UTF-8"
+        } else if too_short {
+            fallbacked = self.fallback_encoding.is_some();
+            self.fallback_encoding.as_deref().unwrap_or("UTF-8")
+        } else if chardet.confidence >= self.confidence_min_for(&charset) && agreement.is_none_or(|a| a >= 1.0) {
+            charset2encoding(&charset)
+        } else if let Some(enc) = self.ask_encoding(bs, &chardet, path) {
+            chosen = true;
+            enc
+        } else if let Some(enc) = self.prioritized_encoding(&self.sample(bs)) {
+            prioritized = true;
+            enc
+        } else if let (Some(enc), true) = (&self.fallback_encoding, self.falls_back_for(&charset)) {
+            fallbacked = true;
+            enc.as_str()
+        } else if self.fallback_encoding.is_some() {
+            info!("not falling back for {} (not in --fallback-for)", charset);
+            fallback_declined = true;
+            charset2encoding(&charset)
+        } else {
+            charset2encoding(&charset)
+        };
+        let mut metadata = Metadata {
+            chardet,
+            encoding: Some(self.style_name(encoding)),
+            fallbacked,
+            prioritized,
+            chosen,
+            fallback_declined,
+            forced: forced.is_some(),
+            forced_encoding: forced.map(|label| label.to_string()),
+            bom_only,
+            bom_detected: bom_encoding(bs).is_some(),
+            too_short,
+            builtin_utf32: utf32.is_some(),
+            non_whatwg: encoding_from_label(encoding).is_some_and(|enc| enc.whatwg_name().is_none()),
+            bytes_swapped,
+            agreement,
+            read_bytes,
+            ..Metadata::default()
+        };
+        let resolved = encoding_from_label(encoding);
+        let endianness = match resolved.map(|enc| enc.name()) {
+            Some("utf-16le") => Some("little"),
+            Some("utf-16be") => Some("big"),
+            _ => None,
+        };
+        if let Some(endianness) = endianness {
+            let from_bom = bom_encoding(bs).is_some_and(|bom| bom.starts_with("UTF-16"));
+            info!(
+                "{}: UTF-16 {} endian, {}",
+                path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy()),
+                endianness,
+                if from_bom {
+                    "from the BOM"
+                } else if utf16.is_some() {
+                    "guessed from the zero byte pattern"
+                } else {
+                    "without a BOM (not checked)"
+                }
+            );
+            metadata.utf16_endianness = Some(endianness.to_string());
+            metadata.utf16_from_bom = from_bom;
+        }
+        // The content is decoded for the checks on it even if only the metadata is shown.
+        let checks_content = self.check_trailing_whitespace
+            || self.language
+            || self.csv_sniff
+            || self.char_stats
+            || self.suspect_mojibake;
+        if self.stat && !checks_content {
+            if !self.json {
+                self.print_metadata(&metadata, path, w)?;
+            }
+            return Ok((metadata, resolved));
+        }
+        let enc = match resolved {
+            Some(e) => e,
+            None => {
+                return Err(DetatError::invalid_input(
+                    InvalidInputErrorKind::NoEncoding(encoding.to_string(), charset.clone()),
+                    format!("no encoding: \"{}\" (charset: \"{}\")", encoding, charset),
+                ));
+            }
+        };
+        if self.stream_output && !self.stat {
+            decode_to_writer(enc, bs, self.decoder_trap, w)?;
+            return Ok((metadata, resolved));
+        }
+        let trap = self.content_trap();
+        let s = if let Some(max_chars) = self.max_chars {
+            let (s, truncated) = decode_limited(enc, bs, trap, max_chars)?;
+            metadata.truncated = truncated;
+            s
+        } else {
+            match enc.decode(bs, trap) {
+                Ok(s) => s,
+                Err(e) => {
+                    return Err(DetatError::decode(e));
+                }
+            }
+        };
+        metadata.replacements = s.matches('\u{FFFD}').count();
+        if self.fail_on_replacement && metadata.replacements > 0 {
+            error!(
+                "{}: {} replacement character(s)",
+                path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy()),
+                metadata.replacements
+            );
+        }
+        if self.show_fallback_diff && fallbacked && !too_short {
+            self.print_fallback_diff(bs, &charset, &s, path)?;
+        }
+        if self.language {
+            metadata.language = detect_language(&s);
+        }
+        if self.csv_sniff {
+            metadata.csv_delimiter = sniff_csv_delimiter(&s).map(|c| c.to_string());
+        }
+        if self.char_stats {
+            metadata.char_stats = Some(CharStats::count(&s));
+        }
+        if self.suspect_mojibake {
+            let misread = mojibake_misread_encoding(&s, enc);
+            if let Some(misread) = misread {
+                warn!(
+                    "{}: looks like UTF-8 decoded as {} (mojibake)",
+                    path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy()),
+                    misread
+                );
+                metadata.suggested_encoding = Some(self.style_name("UTF-8"));
+            }
+            metadata.mojibake_suspected = Some(misread.is_some());
+        }
+        if self.check_trailing_whitespace {
+            let lines = trailing_whitespace_lines(&s);
+            for line in lines.iter() {
+                eprintln!("{}:{}", path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy()), line);
+            }
+            metadata.trailing_whitespace_lines = lines.len();
+        }
+        if self.stat {
+            if !self.json {
+                self.print_metadata(&metadata, path, w)?;
+            }
+            return Ok((metadata, resolved));
+        }
+        let s = self.apply_transforms(s, &mut metadata);
+        if let Some(lines) = self.preview {
+            self.print_preview(bs, &s, &metadata, lines, w)?;
+            return Ok((metadata, resolved));
+        }
+        if let Some(to) = self.to_encoding {
+            // A BOM carried over from the input would not be valid in the output encoding, unless it is a UTF one
+            // and --retain-bom asks for it (the encoder then writes it in the output form).
+            let body = s.trim_start_matches('\u{FEFF}');
+            let bytes = if self.retain_bom && metadata.bom_detected && to.name().starts_with("utf-") {
+                to.encode(&format!("\u{FEFF}{}", body), EncoderTrap::Strict)
+            } else {
+                to.encode(body, EncoderTrap::Strict)
+            }
+            .map_err(DetatError::encode)?;
+            w.write_all(&bytes)?;
+            return Ok((metadata, resolved));
+        }
+        w.write_all(s.as_bytes())?;
+        Ok((metadata, resolved))
+    }
+
+    /// Returns the `--decoder-trap`, or a replacing one with `--detect-only`, whose sample may end in the middle of a
+    /// character in any encoding.
+    fn content_trap(&self) -> DecoderTrap {
+        if self.read_limit.is_some() {
+            DecoderTrap::Replace
+        } else {
+            self.decoder_trap
+        }
+    }
+
+    /// Applies the `--pipeline` transforms and then the registered ones to the decoded content `s`, recording
+    /// what they found in `metadata`.
+    fn apply_transforms(&self, mut s: String, metadata: &mut Metadata) -> String {
+        metadata.final_newline = Some(s.ends_with(&['\n', '\r'][..]));
+        for transform in self.pipeline.iter() {
+            match transform {
+                Transform::TrailingWhitespace => s = strip_trailing_whitespace(&s),
+                Transform::LineEnding => {
+                    let mut line_ending = self.line_ending.unwrap_or(LineEnding::Auto);
+                    if line_ending == LineEnding::Auto {
+                        line_ending = majority_line_ending(&s);
+                    }
+                    s = normalize_line_endings(&s, line_ending.as_str());
+                    metadata.line_ending = Some(line_ending.name().to_string());
+                }
+                Transform::PosixNewline if !s.is_empty() && !s.ends_with(&['\n', '\r'][..]) => {
+                    // In the --line-ending style, which is also the majority style once it has been applied.
+                    s.push_str(self.line_ending.map_or("\n", |line_ending| match line_ending {
+                        LineEnding::Auto => majority_line_ending(&s).as_str(),
+                        line_ending => line_ending.as_str(),
+                    }));
+                }
+                Transform::PosixNewline => {}
+            }
+        }
+        for transform in self.transforms.iter() {
+            s = transform(s);
+        }
+        s
+    }
+
+    /// Returns true if more than `--binary-threshold` of the first `--binary-sample-bytes` of `bs` are binary-like
+    /// (NUL and the other C0 control codes except tab, LF and CR). Inputs with a BOM are never binary-like.
+    pub fn looks_binary(&self, bs: &[u8]) -> bool {
+        let threshold = match self.binary_threshold {
+            Some(threshold) if bom_encoding(bs).is_none() => threshold,
+            _ => return false,
+        };
+        let sample = &bs[..bs.len().min(self.binary_sample_bytes)];
+        if sample.is_empty() {
+            return false;
+        }
+        let count = sample.iter().filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r')).count();
+        count as f32 / sample.len() as f32 > threshold
+    }
+
+    /// Asks on the terminal which encoding an input detected with low confidence is in, showing how the candidates
+    /// decode it, with `--interactive` when stdin is a terminal. Returns `None` to go on without asking.
+    pub fn ask_encoding(&self, bs: &[u8], chardet: &ChardetResult, path: Option<&Path>) -> Option<&'static str> {
+        const COMMON: &[&str] = &[
+            "UTF-8",
+            "windows-1252",
+            "ISO-8859-2",
+            "windows-1251",
+            "KOI8-R",
+            "Shift_JIS",
+            "EUC-JP",
+            "GBK",
+            "Big5",
+            "EUC-KR",
+        ];
+        if !self.interactive || path.is_none() || !io::stdin().is_terminal() {
+            return None;
+        }
+        let mut tty = match fs::OpenOptions::new().read(true).write(true).open("/dev/tty") {
+            Ok(tty) => tty,
+            Err(e) => {
+                warn!("cannot open the terminal: {}", e);
+                return None;
+            }
+        };
+        let guess = charset2encoding(&chardet.charset).to_string();
+        let labels = std::iter::once(guess.as_str())
+            .chain(self.fallback_encoding.as_deref())
+            .chain(self.encoding_priority.iter().map(|label| label.as_str()))
+            .chain(COMMON.iter().copied());
+        let sample = &bs[..bs.len().min(4096)];
+        let mut candidates: Vec<(EncodingRef, String)> = Vec::new();
+        for enc in labels.filter_map(encoding_from_label) {
+            if candidates.len() < 9 && candidates.iter().all(|(c, _)| c.name() != enc.name()) {
+                if let Ok(decoded) = enc.decode(sample, DecoderTrap::Strict) {
+                    candidates.push((enc, decoded));
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return None;
+        }
+        let path = path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy());
+        let prompt = || -> io::Result<Option<usize>> {
+            let mut tty_w = &tty;
+            writeln!(tty_w, "{}: detected {} with low confidence ({})", path, guess, chardet.confidence)?;
+            for (i, (enc, decoded)) in candidates.iter().enumerate() {
+                writeln!(tty_w, "  {}) {}", i + 1, enc.whatwg_name().unwrap_or_else(|| enc.name()))?;
+                for line in decoded.lines().filter(|line| !line.trim().is_empty()).take(3) {
+                    writeln!(tty_w, "     | {}", line.chars().take(72).collect::<String>())?;
+                }
+            }
+            loop {
+                write!(tty_w, "Encoding [1-{}, Enter for 1]: ", candidates.len())?;
+                let mut answer = String::new();
+                if io::BufRead::read_line(&mut io::BufReader::new(&tty), &mut answer)? == 0 {
+                    return Ok(None);
+                }
+                match answer.trim() {
+                    "" => return Ok(Some(0)),
+                    answer => match answer.parse::<usize>() {
+                        Ok(n) if (1..=candidates.len()).contains(&n) => return Ok(Some(n - 1)),
+                        _ => {}
+                    },
+                }
+            }
+        };
+        match prompt() {
+            Ok(choice) => choice.map(|i| candidates[i].0).map(|enc| enc.whatwg_name().unwrap_or_else(|| enc.name())),
+            Err(e) => {
+                warn!("cannot ask for the encoding: {}", e);
+                let _ = tty.flush();
+                None
+            }
+        }
+    }
+
+    /// Returns true if the fallback may replace the unconfident guess `charset`, i.e. `--fallback-for` is not given or
+    /// lists its encoding.
+    fn falls_back_for(&self, charset: &str) -> bool {
+        let guess = charset2encoding(&charset.to_string()).to_string();
+        self.fallback_for.is_empty()
+            || encoding_from_label(&guess).is_some_and(|enc| self.fallback_for.iter().any(|name| *name == enc.name()))
+    }
+
+    /// Returns the first encoding in `--encoding-priority` which decodes `sample` without errors.
+    pub fn prioritized_encoding(&self, sample: &[u8]) -> Option<&str> {
+        self.encoding_priority.iter().map(|label| label.as_str()).find(|&label| {
+            encoding_from_label(label)
+                .and_then(|enc| enc.decode(sample, DecoderTrap::Strict).ok())
+                .is_some_and(|s| !s.contains('\u{FFFD}'))
+        })
+    }
+
+    /// Spells the encoding `label` in the `--name-style`, or returns it as it is, then renames it by `--charset-map`.
+    pub fn style_name(&self, label: &str) -> String {
+        let (style, enc) = match (self.name_style, encoding_from_label(label)) {
+            (Some(style), Some(enc)) => (style, enc),
+            _ => return self.map_charset(label),
+        };
+        let (whatwg, mime, iconv) = names::lookup(enc.name()).unwrap_or_else(|| {
+            let name = enc.whatwg_name().unwrap_or_else(|| enc.name());
+            (name, name, name)
+        });
+        self.map_charset(&match style {
+            NameStyle::Whatwg => whatwg.to_string(),
+            NameStyle::Mime => mime.to_string(),
+            NameStyle::Iconv => iconv.to_string(),
+            NameStyle::Lower => whatwg.to_ascii_lowercase(),
+            NameStyle::Upper => whatwg.to_ascii_uppercase(),
+        })
+    }
+
+    /// Returns the `--charset-map` label for `name` (compared case-insensitively), or `name` if unmapped.
+    fn map_charset(&self, name: &str) -> String {
+        self.charset_map.get(&name.to_ascii_lowercase()).cloned().unwrap_or_else(|| name.to_string())
+    }
+
+    /// Renames every `charset` (as detected by chardet) in `value` by `--charset-map`.
+    fn map_charset_fields(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Array(xs) => xs.iter_mut().for_each(|x| self.map_charset_fields(x)),
+            serde_json::Value::Object(m) => {
+                for (key, value) in m.iter_mut() {
+                    match value {
+                        serde_json::Value::String(charset) if key == "charset" => *charset = self.map_charset(charset),
+                        value => self.map_charset_fields(value),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the `--conf-threshold` for the encoding of `charset`, or `--confidence-min` if unlisted.
+    pub fn confidence_min_for(&self, charset: &str) -> f32 {
+        let name = match encoding_from_label(charset2encoding(&charset.to_string())) {
+            Some(enc) => enc.name(),
+            None => return self.confidence_min,
+        };
+        self.confidence_thresholds.iter().find(|ct| ct.encoding == name).map_or(self.confidence_min, |ct| ct.threshold)
+    }
+
+    /// Returns the encoding of the first `--path-encoding` rule matching `path`.
+    pub fn path_encoding(&self, path: &Path) -> Option<&str> {
+        let path = path.to_string_lossy();
+        self.path_encodings.iter().find(|pe| pe.regex.is_match(&path)).map(|pe| pe.encoding.as_str())
+    }
+
+    /// Prints the lines which differ between the decoding with the detector's guess and the fallback `decoded` to
+    /// stderr, colored if it is a terminal.
+    pub fn print_fallback_diff(&self, bs: &[u8], charset: &str, decoded: &str, path: Option<&Path>) -> DetatResult<()> {
+        let charset = charset.to_string();
+        let guess = charset2encoding(&charset);
+        let guessed = match encoding_from_label(guess) {
+            Some(enc) => enc.decode(bs, DecoderTrap::Replace).map_err(DetatError::decode)?,
+            None => {
+                warn!("no encoding for the guess: \"{}\"", charset);
+                return Ok(());
+            }
+        };
+        let (del, add, reset) =
+            if io::stderr().is_terminal() { ("\x1b[31m", "\x1b[32m", "\x1b[0m") } else { ("", "", "") };
+        let stderr = io::stderr();
+        let mut w = stderr.lock();
+        writeln!(w, "--- {} ({})", path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy()), guess)?;
+        writeln!(
+            w,
+            "+++ {} ({})",
+            path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy()),
+            self.fallback_encoding.as_deref().unwrap_or("-")
+        )?;
+        diff::write_line_diff(&guessed, decoded, (del, add, reset), &mut w)?;
+        Ok(())
+    }
+
+    /// Decodes `path` (`""` or `"-"` for stdin) the same way as it would be output, without any banner or BOM,
+    /// returning it with its metadata.
+    pub fn decode_path(&self, path: &Path) -> DetatResult<(String, Metadata)> {
+        let (bs, path) = if is_stdin(path) {
+            (self.read_input(&mut io::stdin().lock())?, None)
+        } else {
+            (self.read_input(&mut File::open(path)?)?, Some(path))
+        };
+        let mut buf = Vec::new();
+        let metadata = self.copy_bytes(&bs, path, &mut buf)?;
+        Ok((String::from_utf8_lossy(&buf).trim_start_matches('\u{FEFF}').to_owned(), metadata))
+    }
+
+    /// Prints the lines in which the decoded `path` differs from the decoded `reference` to stdout, colored if it
+    /// is a terminal. Returns whether they are the same, and the metadata of `path`.
+    pub fn run_diff_against(&self, path: &Path, reference: &(PathBuf, String)) -> DetatResult<(bool, Metadata)> {
+        let (decoded, metadata) = self.decode_path(path)?;
+        let (reference_path, reference) = reference;
+        if decoded == *reference {
+            return Ok((true, metadata));
+        }
+        let stdout = io::stdout();
+        let colors = if stdout.is_terminal() { ("\x1b[31m", "\x1b[32m", "\x1b[0m") } else { ("", "", "") };
+        let mut w = stdout.lock();
+        writeln!(w, "--- {}", reference_path.to_string_lossy())?;
+        writeln!(w, "+++ {}", if is_stdin(path) { Cow::Borrowed("-") } else { path.to_string_lossy() })?;
+        diff::write_line_diff(reference, &decoded, colors, &mut w)?;
+        Ok((false, metadata))
+    }
+
+    pub fn print_preview<W: Write>(
+        &self,
+        bs: &[u8],
+        s: &str,
+        metadata: &Metadata,
+        lines: usize,
+        w: &mut W,
+    ) -> Result<(), io::Error> {
+        let raws: Vec<String> = bs
+            .strip_suffix(b"\n")
+            .unwrap_or(bs)
+            .split(|&b| b == b'\n')
+            .take(lines)
+            .map(|line| {
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                line.iter().map(|&b| if b < 0x20 || (0x7f..0xa0).contains(&b) { '.' } else { b as char }).collect()
+            })
+            .collect();
+        let width = raws.iter().map(|raw| raw.chars().count()).max().unwrap_or(0).max("Latin-1".len());
+        writeln!(w, "{:width$} | {}", "Latin-1", metadata.encoding.as_deref().unwrap_or("-"), width = width)?;
+        let mut decoded = s.lines();
+        for raw in raws.iter() {
+            writeln!(w, "{:width$} | {}", raw, decoded.next().unwrap_or(""), width = width)?;
+        }
+        Ok(())
+    }
+
+    pub fn print_metadata<W: Write>(
+        &self,
+        metadata: &Metadata,
+        path: Option<&Path>,
+        w: &mut W,
+    ) -> Result<(), io::Error> {
+        writeln!(w, "---")?;
+        writeln!(w, "Path: {}", path.and_then(|p| p.to_str()).unwrap_or("-"))?;
+        if metadata.is_empty {
+            writeln!(w, "Charset: (empty file)")?;
+            return Ok(());
+        }
+        // The detector's own name, unless --name-style asks for a spelling of its encoding.
+        let charset = match self.name_style {
+            Some(_) => self.style_name(charset2encoding(&metadata.chardet.charset)),
+            None => self.map_charset(&metadata.chardet.charset),
+        };
+        writeln!(w, "Charset: {}", charset)?;
+        writeln!(w, "Confidence: {}", metadata.chardet.confidence)?;
+        writeln!(w, "Language: {}", metadata.chardet.language)?;
+        Ok(())
+    }
+
+    pub fn output<R: Read>(&self, r: &mut R, path: Option<&Path>) -> DetatResult<Output> {
+        let bs = self.read_input(r)?;
+        self.output_bytes(&bs, path)
+    }
+
+    fn output_bytes(&self, bs: &[u8], path: Option<&Path>) -> DetatResult<Output> {
+        let mut content: Vec<u8> = Vec::new();
+        // The sample is decoded from the same bytes as the content.
+        let bs = &*self.swap_input(bs)?;
+        let (metadata, enc) = self.copy_bytes_with_encoding(bs, path, &mut content)?;
+        let debug = if self.debug_json { Some(self.debug_info(bs, &metadata)) } else { None };
+        let path = path.and_then(|p| p.to_str()).map(|s| s.to_owned());
+        let content = if self.stat || (metadata.encoding.is_none() && !metadata.is_empty) {
+            None
+        } else {
+            String::from_utf8(content).ok()
+        };
+        let trap = self.content_trap();
+        let sample = match (self.json_sample, enc) {
+            (Some(chars), Some(enc)) => decode_limited(enc, bs, trap, chars).ok().map(|(s, _)| s),
+            _ => None,
+        };
+        // serde_json writes NUL as `\u0000`, which is valid JSON but cut short by C-string based consumers.
+        let escape_nul = |s: Option<String>| if self.escape_nul { s.map(|s| s.replace('\0', "^@")) } else { s };
+        let (content, sample) = (escape_nul(content), escape_nul(sample));
+        Ok(Output { metadata, path, content, sample, debug, ..Output::default() })
+    }
+
+    /// Returns the detection details of `bs` as detected and decoded (i.e. after `--swap-bytes`) into `metadata`.
+    pub fn debug_info(&self, bs: &[u8], metadata: &Metadata) -> DebugInfo {
+        let charset = &metadata.chardet.charset;
+        DebugInfo {
+            sample_bytes: self.sample(bs).len(),
+            bom: bom_encoding(bs).map(|s| s.to_string()),
+            confidence_min: self.confidence_min_for(charset),
+            fallback_encoding: self.fallback_encoding.clone(),
+            forced_encoding: metadata.forced_encoding.clone(),
+            guess: if charset.is_empty() { None } else { Some(self.style_name(charset2encoding(charset))) },
+            encoding: metadata.encoding.clone(),
+        }
+    }
+
+    pub fn copy_as_json<R: Read, W: Write>(&self, r: &mut R, path: Option<&Path>, w: &mut W) -> DetatResult<Metadata> {
+        let output = self.output(r, path)?;
+        self.write_output_record(&output, w)?;
+        Ok(output.metadata)
+    }
+
+    /// Writes `output` as a JSON record, unless the input is empty and `--skip-empty-json` is given. Otherwise an
+    /// empty input is written with `is_empty: true`, `read_bytes: 0` and `content: ""`.
+    fn write_output_record<W: Write>(&self, output: &Output, w: &mut W) -> io::Result<()> {
+        if self.skip_empty_json && output.metadata.is_empty {
+            info!("{}: skipped empty input", output.path.as_deref().unwrap_or("-"));
+            return Ok(());
+        }
+        self.write_json_record(output, w)
+    }
+
+    /// Serializes `value`, whose `metadata` and `summary` objects (at any level) are subject to
+    /// `--json-numbers-as-strings`.
+    fn to_json<T: Serialize>(&self, value: &T) -> Vec<u8> {
+        self.serialize_json(value, false)
+    }
+
+    /// Serializes a `Metadata` or `Summary` on its own (e.g. for `--emit-meta-sidecar` and `--summary-json`), all of
+    /// whose numbers are subject to `--json-numbers-as-strings`.
+    fn metadata_to_json<T: Serialize>(&self, value: &T) -> Vec<u8> {
+        self.serialize_json(value, true)
+    }
+
+    /// Going through `serde_json::Value` (for `--json-numbers-as-strings`, `--json-ordered` or `--charset-map`) sorts
+    /// object keys lexicographically at every level, which is the documented order of `--json-ordered`.
+    fn serialize_json<T: Serialize>(&self, value: &T, is_metadata: bool) -> Vec<u8> {
+        let pretty = self.record_separator.is_some();
+        if self.json_numbers_as_strings || self.json_ordered || !self.charset_map.is_empty() {
+            let mut value = serde_json::to_value(value).unwrap();
+            if self.json_numbers_as_strings {
+                if is_metadata {
+                    stringify_numbers(&mut value);
+                } else {
+                    stringify_metadata_numbers(&mut value);
+                }
+            }
+            self.map_charset_fields(&mut value);
+            if pretty { serde_json::to_vec_pretty(&value) } else { serde_json::to_vec(&value) }.unwrap()
+        } else if pretty {
+            serde_json::to_vec_pretty(value).unwrap()
+        } else {
+            serde_json::to_vec(value).unwrap()
+        }
+    }
+
+    /// Writes `value` as a line of JSON Lines, or as a pretty-printed record prefixed by `--record-separator` with
+    /// `--json-seq`.
+    fn write_json_record<T: Serialize, W: Write>(&self, value: &T, w: &mut W) -> io::Result<()> {
+        if let Some(separator) = &self.record_separator {
+            w.write_all(separator.as_bytes())?;
+        }
+        w.write_all(&self.to_json(value))?;
+        writeln!(w)
+    }
+
+    /// Writes an `xxd`-style dump of `bs` line by line, headed by the detected encoding.
+    pub fn hex_dump<W: Write>(&self, bs: &[u8], path: Option<&Path>, w: &mut W) -> DetatResult<Metadata> {
+        let chardet = self.detect(&self.sample(bs));
+        let encoding =
+            if chardet.charset.is_empty() { None } else { Some(self.style_name(charset2encoding(&chardet.charset))) };
+        writeln!(w, "# Path: {}", path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy()))?;
+        writeln!(w, "# Encoding: {} (confidence: {})", encoding.as_deref().unwrap_or("-"), chardet.confidence)?;
+        for (i, line) in bs.chunks(16).enumerate() {
+            let hex: Vec<String> =
+                line.chunks(2).map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect()).collect();
+            let ascii: String = line.iter().map(|&b| if (0x20..0x7f).contains(&b) { b as char } else { '.' }).collect();
+            writeln!(w, "{:08x}: {:<39}  {}", i * 16, hex.join(" "), ascii)?;
+        }
+        Ok(Metadata { chardet, encoding, is_empty: bs.is_empty(), read_bytes: bs.len(), ..Metadata::default() })
+    }
+
+    pub fn detect_windows<R: Read, W: Write>(&self, r: &mut R, size: usize, w: &mut W) -> DetatResult<Metadata> {
+        if size == 0 {
+            return Err(DetatError::invalid_opt("window size must be greater than 0".to_string()));
+        }
+        let mut bs = Vec::new();
+        let read_bytes = r.read_to_end(&mut bs)?;
+        let windows: Vec<WindowResult> = bs
+            .chunks(size)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let chardet = self.detect(chunk);
+                WindowResult {
+                    offset: i * size,
+                    encoding: self.style_name(charset2encoding(&chardet.charset)),
+                    confidence: chardet.confidence,
+                    has_confidence: !chardet.charset.is_empty()
+                        && chardet.confidence >= self.confidence_min_for(&chardet.charset),
+                    windows: None,
+                }
+            })
+            .collect();
+        let windows = if self.merge_segments { merge_windows(windows) } else { windows };
+        self.write_json_record(&windows, w)?;
+        let chardet = self.detect(bs.as_slice());
+        let encoding =
+            if chardet.charset.is_empty() { None } else { Some(self.style_name(charset2encoding(&chardet.charset))) };
+        Ok(Metadata { chardet, encoding, is_empty: bs.is_empty(), read_bytes, ..Metadata::default() })
+    }
+
+    /// Renames `path` if its file name is not UTF-8, decoding it with the detected encoding.
+    #[cfg(unix)]
+    pub fn fix_filename(&self, path: &Path) -> DetatResult<()> {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let name = match path.file_name() {
+            Some(name) if std::str::from_utf8(name.as_bytes()).is_err() => name,
+            _ => return Ok(()),
+        };
+        // Only the name itself is decoded; none of the content options (transforms, --to-encoding, ...) apply.
+        let chardet = self.detect(name.as_bytes());
+        let label = self.from_encoding.as_deref().unwrap_or_else(|| charset2encoding(&chardet.charset));
+        let enc = encoding_from_label(label).ok_or_else(|| {
+            DetatError::invalid_input(
+                InvalidInputErrorKind::NoEncoding(label.to_string(), chardet.charset.clone()),
+                format!("no encoding: \"{}\" (charset: \"{}\")", label, chardet.charset),
+            )
+        })?;
+        let decoded = enc.decode(name.as_bytes(), DecoderTrap::Strict).map_err(DetatError::decode)?.into_bytes();
+        if decoded.is_empty() || decoded.iter().any(|&b| b == b'/' || b == 0) {
+            return Err(DetatError::invalid_input(
+                InvalidInputErrorKind::InvalidFileName(decoded.clone()),
+                format!("invalid file name: {}", String::from_utf8_lossy(&decoded)),
+            ));
+        }
+        let new_path = path.with_file_name(OsStr::from_bytes(&decoded));
+        if new_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", new_path.to_string_lossy()),
+            )
+            .into());
+        }
+        let stdout = io::stdout();
+        writeln!(stdout.lock(), "{} -> {}", path.to_string_lossy(), new_path.to_string_lossy())?;
+        if !self.dry_run {
+            rename_no_replace(path, &new_path)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn fix_filename(&self, _path: &Path) -> DetatResult<()> {
+        Err(DetatError::invalid_opt("--fix-filenames is only supported on Unix".to_string()))
+    }
+
+    #[cfg(unix)]
+    pub fn copy_from_fd<W: Write>(&self, fd: i32, w: &mut W) -> DetatResult<Metadata> {
+        use std::os::unix::io::FromRawFd;
+        if fd < 0 {
+            return Err(DetatError::invalid_opt(format!("invalid file descriptor: {}", fd)));
+        }
+        // The descriptor is borrowed, not owned: it is left open, which matters for `--fd 1` or `--fd 2`.
+        let mut file = std::mem::ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+        if let Err(e) = file.metadata() {
+            return Err(DetatError::invalid_opt(format!("invalid file descriptor: {}: {}", fd, e)));
+        }
+        self.copy_by_mode(&mut *file, None, w)
+    }
+
+    #[cfg(not(unix))]
+    pub fn copy_from_fd<W: Write>(&self, _fd: i32, _w: &mut W) -> DetatResult<Metadata> {
+        Err(DetatError::invalid_opt("--fd is only supported on Unix".to_string()))
+    }
+
+    /// Feeds the detector `r` in small reads until its guess is confident, leaving the rest of `r` unread. Pure
+    /// ASCII is never taken as confident, as anything may follow it.
+    pub fn probe<R: Read>(&self, r: &mut R) -> io::Result<ProbeResult> {
+        const CHUNK_SIZE: usize = 1024;
+        const MIN_CONFIDENCE: f32 = 0.9;
+        let mut detector = self.detector.borrow_mut();
+        detector.reset();
+        let mut buf = [0u8; CHUNK_SIZE];
+        let mut read_bytes = 0;
+        loop {
+            let n = match r.read(&mut buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            read_bytes += n;
+            detector.feed(&buf[..n]);
+            // `close` only reports the current guess, so feeding can go on afterwards.
+            let chardet = ChardetResult::from_tuple(detector.close());
+            let confident = !chardet.charset.is_empty()
+                && chardet.charset != "ascii"
+                && chardet.confidence >= self.confidence_min_for(&chardet.charset).max(MIN_CONFIDENCE);
+            if confident || n == 0 {
+                let encoding = if chardet.charset.is_empty() {
+                    None
+                } else {
+                    Some(self.style_name(charset2encoding(&chardet.charset)))
+                };
+                return Ok(ProbeResult { chardet, encoding, confident, read_bytes });
+            }
+        }
+    }
+
+    /// Probes stdin, reading it unbuffered so that what follows the probed part is left to the next reader.
+    pub fn run_probe(&self) -> DetatResult<bool> {
+        #[cfg(unix)]
+        let result = {
+            use std::os::unix::io::FromRawFd;
+            let mut stdin = std::mem::ManuallyDrop::new(unsafe { File::from_raw_fd(0) });
+            self.probe(&mut *stdin)?
+        };
+        #[cfg(not(unix))]
+        let result = self.probe(&mut io::stdin().lock())?;
+        let stdout = io::stdout();
+        let mut w = stdout.lock();
+        if self.json {
+            self.write_json_record(&result, &mut w)?;
+        } else {
+            let encoding = result.encoding.as_deref().unwrap_or("-");
+            writeln!(w, "{}\t{}\t{}", encoding, result.chardet.confidence, result.read_bytes)?;
+        }
+        Ok(result.confident)
+    }
+
+    pub fn copy_from_stdin<W: Write>(&self, w: &mut W) -> DetatResult<Metadata> {
+        let stdin = io::stdin();
+        let mut handle = stdin.lock();
+        self.copy_by_mode(&mut handle, None, w)
+    }
+
+    pub fn copy_from_file<W: Write>(&self, path: &Path, w: &mut W) -> DetatResult<Metadata> {
+        let mut file = File::open(path)?;
+        self.copy_by_mode(&mut file, Some(path), w)
+    }
+
+    fn copy_by_mode<R: Read, W: Write>(&self, r: &mut R, path: Option<&Path>, w: &mut W) -> DetatResult<Metadata> {
+        if let Some(size) = self.window_size {
+            self.detect_windows(r, size, w)
+        } else if self.hex_dump {
+            let bs = self.read_input(r)?;
+            self.hex_dump(&bs, path, w)
+        } else if self.json {
+            self.copy_as_json(r, path, w)
+        } else {
+            self.copy(r, path, w)
+        }
+    }
+
+    pub fn run(&self, path: &Path) -> DetatResult<Metadata> {
+        let stdout = io::stdout();
+        let w = stdout.lock();
+        let mut bw = BufWriter::new(w);
+        self.run_with(path, &mut bw)
+    }
+
+    /// Does everything `run` does for `path` (`""` or `"-"` for stdin), writing to `w` instead of stdout and
+    /// returning the full `Output`. `content` is only set when the decoded text itself is written.
+    pub fn process_path<W: Write>(&self, path: &Path, w: &mut W) -> DetatResult<Output> {
+        let (bs, partial, path) = if is_stdin(path) {
+            let (bs, partial) = self.read_with_timeout(io::stdin(), None)?;
+            (bs, partial, None)
+        } else {
+            let (bs, partial) = self.read_with_timeout(File::open(path)?, Some(path))?;
+            (bs, partial, Some(path))
+        };
+        let mut output = if let Some(size) = self.window_size {
+            let metadata = self.detect_windows(&mut bs.as_slice(), size, w)?;
+            Output { path: path.and_then(|p| p.to_str()).map(|s| s.to_owned()), metadata, ..Output::default() }
+        } else if self.hex_dump {
+            let metadata = self.hex_dump(&bs, path, w)?;
+            Output { path: path.and_then(|p| p.to_str()).map(|s| s.to_owned()), metadata, ..Output::default() }
+        } else if self.json {
+            let mut output = self.output_bytes(&bs, path)?;
+            output.metadata.partial = partial;
+            self.write_output_record(&output, w)?;
+            output
+        } else if self.stream_output {
+            let name = path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy());
+            let metadata = match self.filename_style.filter(|_| !self.stat) {
+                Some(FilenameStyle::Prefix) => {
+                    let mut pw = PrefixWriter { inner: &mut *w, prefix: format!("{}: ", name), line_start: true };
+                    self.copy_bytes(&bs, path, &mut pw)?
+                }
+                Some(style) => {
+                    // The encoding is not known yet when streaming.
+                    self.write_banner(style, &name, None, w)?;
+                    self.copy_bytes(&bs, path, w)?
+                }
+                None => self.copy_bytes(&bs, path, w)?,
+            };
+            Output { path: path.and_then(|p| p.to_str()).map(|s| s.to_owned()), metadata, ..Output::default() }
+        } else {
+            let mut buf = Vec::new();
+            let metadata = self.copy_bytes(&bs, path, &mut buf)?;
+            let name = path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy());
+            match self.filename_style.filter(|_| !self.stat) {
+                Some(FilenameStyle::Prefix) => {
+                    PrefixWriter { inner: &mut *w, prefix: format!("{}: ", name), line_start: true }.write_all(&buf)?
+                }
+                Some(style) => {
+                    self.write_banner(style, &name, Some(&metadata), w)?;
+                    w.write_all(&buf)?
+                }
+                None => w.write_all(&buf)?,
+            }
+            let content = if self.stat || self.preview.is_some() { None } else { String::from_utf8(buf).ok() };
+            Output { path: path.and_then(|p| p.to_str()).map(|s| s.to_owned()), metadata, content, ..Output::default() }
+        };
+        output.metadata.partial = partial;
+        if let (true, Some(path)) = (self.emit_meta_sidecar, path) {
+            self.write_meta_sidecar(path, &output.metadata)?;
+        }
+        self.check_confidence(&output.metadata, path)?;
+        Ok(output)
+    }
+
+    /// Writes a tail-style `==> name <==` banner or a `===== name (encoding) =====` header, separated from the
+    /// previous input by an empty line.
+    fn write_banner<W: Write>(
+        &self,
+        style: FilenameStyle,
+        name: &str,
+        metadata: Option<&Metadata>,
+        w: &mut W,
+    ) -> io::Result<()> {
+        if self.banner_written.replace(true) {
+            writeln!(w)?;
+        }
+        match (style, metadata) {
+            (FilenameStyle::Header, Some(metadata)) => {
+                let encoding = if metadata.is_empty {
+                    "empty"
+                } else if metadata.binary {
+                    "binary"
+                } else {
+                    metadata.encoding.as_deref().unwrap_or("unknown")
+                };
+                writeln!(w, "===== {} ({}) =====", name, encoding)
+            }
+            (FilenameStyle::Header, None) => writeln!(w, "===== {} =====", name),
+            _ => writeln!(w, "==> {} <==", name),
+        }
+    }
+
+    /// Writes `metadata` as JSON to `<path>.meta.json`.
+    fn write_meta_sidecar(&self, path: &Path, metadata: &Metadata) -> DetatResult<()> {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".meta.json");
+        if self.dry_run {
+            info!("would write {}", sidecar.to_string_lossy());
+            return Ok(());
+        }
+        let mut json = self.metadata_to_json(metadata);
+        json.push(b'\n');
+        fs::write(sidecar, json)?;
+        Ok(())
+    }
+
+    /// Prints whether `path` is valid UTF-8 without running the detector, returning the metadata (with UTF-8 as the
+    /// encoding), or a decode error if it is not valid.
+    pub fn run_utf8_validity(&self, path: &Path) -> DetatResult<Metadata> {
+        let (bs, path) = if is_stdin(path) {
+            (self.read_input(&mut io::stdin().lock())?, None)
+        } else {
+            (self.read_input(&mut File::open(path)?)?, Some(path))
+        };
+        let error_offset = std::str::from_utf8(&bs).err().map(|e| e.valid_up_to());
+        let validity = Utf8Validity {
+            path: path.and_then(|p| p.to_str()).map(|s| s.to_owned()),
+            valid: error_offset.is_none(),
+            error_offset,
+        };
+        let stdout = io::stdout();
+        let mut w = stdout.lock();
+        if self.json {
+            self.write_json_record(&validity, &mut w)?;
+        } else {
+            let path = path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy());
+            match error_offset {
+                None => writeln!(w, "{}: valid", path)?,
+                Some(offset) => writeln!(w, "{}: invalid at byte {}", path, offset)?,
+            }
+        }
+        if let Some(offset) = error_offset {
+            let path = path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy());
+            return Err(DetatError::decode(format!("{}: invalid UTF-8 at byte {}", path, offset).into()));
+        }
+        Ok(Metadata {
+            encoding: Some(self.style_name("UTF-8")),
+            is_empty: bs.is_empty(),
+            read_bytes: bs.len(),
+            ..Metadata::default()
+        })
+    }
+
+    /// Writes the content of the `index`th of `count` inputs to its `--split-output` file, which is only created
+    /// once the input has been processed.
+    pub fn run_split(&self, path: &Path, index: usize, count: usize, prefix: &str) -> DetatResult<Metadata> {
+        let out_path = split_output_path(prefix, index, count);
+        let mut buf = Vec::new();
+        let metadata = self.run_with(path, &mut buf)?;
+        fs::write(&out_path, buf)?;
+        let stdout = io::stdout();
+        let mut w = stdout.lock();
+        writeln!(w, "{}\t{}\t{}", out_path, metadata.encoding.as_deref().unwrap_or("-"), path.to_str().unwrap_or("-"))?;
+        Ok(metadata)
+    }
+
+    pub fn print_groups(&self, groups: &[(Option<String>, Vec<u8>)]) -> DetatResult<()> {
+        let stdout = io::stdout();
+        let w = stdout.lock();
+        let mut bw = BufWriter::new(w);
+        for (encoding, content) in groups.iter() {
+            if !self.json {
+                writeln!(bw, "=== {} ===", encoding.as_deref().unwrap_or("-"))?;
+            }
+            bw.write_all(content)?;
+        }
+        Ok(())
+    }
+
+    /// Processes each regular file in the tar archive at `path`, returning the path and the metadata (or the error)
+    /// of each entry.
+    #[cfg(feature = "tar")]
+    pub fn run_tar(&self, path: &Path) -> DetatResult<Vec<(String, DetatResult<Metadata>)>> {
+        let stdout = io::stdout();
+        let w = stdout.lock();
+        let mut bw = BufWriter::new(w);
+        let stdin = io::stdin();
+        let r: Box<dyn Read> = if is_stdin(path) { Box::new(stdin.lock()) } else { Box::new(File::open(path)?) };
+        let mut results = Vec::new();
+        for entry in tar::Archive::new(r) {
+            let entry = entry?;
+            let entry_path = Path::new(&entry.path);
+            let result = self
+                .copy_by_mode(&mut entry.data.as_slice(), Some(entry_path), &mut bw)
+                .and_then(|metadata| self.check_confidence(&metadata, Some(entry_path)).map(|_| metadata));
+            match result {
+                Err(e) if e.is_broken_pipe() => return Err(e),
+                result => results.push((entry.path, result)),
+            }
+        }
+        Ok(results)
+    }
+
+    /// Reads `Output`s as JSON Lines (e.g. from `--json`) from stdin and writes them back with the content
+    /// transforms applied to their `content`, returning the number of lines that could not be read.
+    pub fn run_from_jsonl(&self) -> DetatResult<usize> {
+        let stdout = io::stdout();
+        let w = stdout.lock();
+        let mut bw = BufWriter::new(w);
+        let stdin = io::stdin();
+        let mut failures = 0;
+        for (i, line) in io::BufRead::lines(stdin.lock()).enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Output>(&line) {
+                Ok(mut output) => {
+                    if let Some(content) = output.content.take() {
+                        output.content = Some(self.apply_transforms(content, &mut output.metadata));
+                    }
+                    self.write_json_record(&output, &mut bw)?;
+                }
+                Err(e) => {
+                    let e = DetatError::invalid_input(InvalidInputErrorKind::InvalidJson, e.to_string());
+                    error!("-:{}: {}", i + 1, e);
+                    failures += 1;
+                }
+            }
+        }
+        Ok(failures)
+    }
+
+    /// Processes each file in the zip archive at `path`, returning the path and the metadata (or the error) of each
+    /// entry. The encoding of each entry name (UTF-8 if flagged so, otherwise CP437) is reported as `name_encoding`.
+    #[cfg(feature = "zip")]
+    pub fn run_zip(&self, path: &Path) -> DetatResult<Vec<(String, DetatResult<Metadata>)>> {
+        let stdout = io::stdout();
+        let w = stdout.lock();
+        let mut bw = BufWriter::new(w);
+        let bs = if is_stdin(path) { self.read_input(&mut io::stdin().lock())? } else { fs::read(path)? };
+        let archive = zip::Archive::new(bs);
+        let mut results = Vec::new();
+        for entry in archive.entries()? {
+            let entry_path = Path::new(&entry.path);
+            let name_encoding = self.style_name(if entry.utf8_name { "UTF-8" } else { cp437::IBM437.name });
+            let result = archive.data(&entry).map_err(DetatError::from).and_then(|data| {
+                let metadata = if self.json && self.window_size.is_none() && !self.hex_dump {
+                    let mut output = self.output_bytes(&data, Some(entry_path))?;
+                    output.name_encoding = Some(name_encoding);
+                    self.write_output_record(&output, &mut bw)?;
+                    output.metadata
+                } else {
+                    info!("{}: name in {}", entry.path, name_encoding);
+                    self.copy_by_mode(&mut data.as_slice(), Some(entry_path), &mut bw)?
+                };
+                self.check_confidence(&metadata, Some(entry_path)).map(|_| metadata)
+            });
+            match result {
+                Err(e) if e.is_broken_pipe() => return Err(e),
+                result => results.push((entry.path, result)),
+            }
+        }
+        Ok(results)
+    }
+
+    #[cfg(not(feature = "tar"))]
+    pub fn run_tar(&self, _path: &Path) -> DetatResult<Vec<(String, DetatResult<Metadata>)>> {
+        Err(missing_feature("tar"))
+    }
+
+    #[cfg(not(feature = "zip"))]
+    pub fn run_zip(&self, _path: &Path) -> DetatResult<Vec<(String, DetatResult<Metadata>)>> {
+        Err(missing_feature("zip"))
+    }
+
+    /// Prints whether the encoding of `path` was detected confidently, taking failures as not. Returns the
+    /// metadata unless it failed.
+    pub fn run_confident(&self, path: &Path) -> DetatResult<Option<Metadata>> {
+        let metadata = match self.process_path(path, &mut io::sink()) {
+            Ok(output) => Some(output.metadata),
+            Err(e) if e.is_broken_pipe() => return Err(e),
+            Err(e) => {
+                info!("{}: {}", path.to_string_lossy(), e);
+                None
+            }
+        };
+        let confident = metadata.as_ref().is_some_and(|metadata| {
+            metadata.is_confident() && metadata.chardet.confidence >= self.confidence_min_for(&metadata.chardet.charset)
+        });
+        let stdout = io::stdout();
+        let mut w = stdout.lock();
+        writeln!(w, "{}", confident)?;
+        Ok(metadata)
+    }
+
+    pub fn run_fd(&self, fd: i32) -> DetatResult<Metadata> {
+        let stdout = io::stdout();
+        let w = stdout.lock();
+        let mut bw = BufWriter::new(w);
+        let metadata = self.copy_from_fd(fd, &mut bw)?;
+        self.check_confidence(&metadata, None)?;
+        Ok(metadata)
+    }
+
+    fn run_with<W: Write>(&self, path: &Path, w: &mut W) -> DetatResult<Metadata> {
+        self.process_path(path, w).map(|output| output.metadata)
+    }
+
+    fn check_confidence(&self, metadata: &Metadata, path: Option<&Path>) -> DetatResult<()> {
+        let confidence = metadata.chardet.confidence;
+        let confidence_min = self.confidence_min_for(&metadata.chardet.charset);
+        if metadata.read_bytes > 0
+            && !metadata.is_empty
+            && !metadata.binary
+            && !metadata.too_short
+            && !metadata.fallbacked
+            && !metadata.prioritized
+            && !metadata.chosen
+            && !metadata.fallback_declined
+            && !metadata.forced
+            && confidence < confidence_min
+        {
+            return Err(DetatError::invalid_input(
+                InvalidInputErrorKind::LowConfidence(metadata.chardet.charset.clone(), confidence, confidence_min),
+                format!("confidence: {} < {} (predicted: {})", confidence, confidence_min, metadata.chardet.charset),
+            ));
+        }
+        if let Some(agreement) = metadata.agreement {
+            if agreement < 1.0 && !metadata.fallbacked && !metadata.forced {
+                return Err(DetatError::invalid_input(
+                    InvalidInputErrorKind::Disagreement(metadata.chardet.charset.clone(), agreement),
+                    format!(
+                        "detection passes disagree: agreement {} (predicted: {})",
+                        agreement, metadata.chardet.charset
+                    ),
+                ));
+            }
+        }
+        if metadata.trailing_whitespace_lines > 0 {
+            return Err(DetatError::invalid_input(
+                InvalidInputErrorKind::TrailingWhitespace(metadata.trailing_whitespace_lines),
+                format!("trailing whitespace in {} line(s)", metadata.trailing_whitespace_lines),
+            ));
+        }
+        let has_content = metadata.encoding.is_some() || metadata.bom_only;
+        if has_content && metadata.bom_detected != self.require_bom && (self.require_bom || self.forbid_bom) {
+            let path = path.map_or(Cow::Borrowed("-"), |p| p.to_string_lossy());
+            let (kind, message) = if self.require_bom {
+                (InvalidInputErrorKind::MissingBom, format!("{}: no byte order mark", path))
+            } else {
+                (InvalidInputErrorKind::UnexpectedBom, format!("{}: unexpected byte order mark", path))
+            };
+            return Err(DetatError::invalid_input(kind, message));
+        }
+        Ok(())
+    }
+
+    pub fn print_report(&self, report: &Report) -> DetatResult<()> {
+        let stdout = io::stdout();
+        let mut w = stdout.lock();
+        w.write_all(&self.to_json(report))?;
+        writeln!(w)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "language")]
+fn detect_language(s: &str) -> Option<String> {
+    language::detect(s).map(|l| l.to_string())
+}
+
+#[cfg(not(feature = "language"))]
+fn detect_language(_s: &str) -> Option<String> {
+    None
+}
+
+/// Returns `<prefix>.NNN` for the `index`th of `count` inputs, padding the index to the same number of digits (at
+/// least 3) for all of them so that the files sort in input order.
+fn split_output_path(prefix: &str, index: usize, count: usize) -> String {
+    let width = count.saturating_sub(1).to_string().len().max(3);
+    format!("{}.{:0width$}", prefix, index, width = width)
+}
+
+/// Decodes `bs` chunk by chunk, writing each decoded chunk to `w` as soon as it is available.
+fn decode_to_writer<W: Write>(enc: EncodingRef, bs: &[u8], trap: DecoderTrap, w: &mut W) -> DetatResult<()> {
+    decode_chunks(enc, bs, trap, None, |s| w.write_all(s.as_bytes())).map(|_| ())
+}
+
+/// Decodes `bs` chunk by chunk until `max_chars` characters have been produced, returning them and whether the
+/// rest was left undecoded.
+fn decode_limited(enc: EncodingRef, bs: &[u8], trap: DecoderTrap, max_chars: usize) -> DetatResult<(String, bool)> {
+    let mut out = String::new();
+    let truncated = decode_chunks(enc, bs, trap, Some(max_chars), |s| {
+        out.push_str(s);
+        Ok(())
+    })?;
+    Ok((out, truncated))
+}
+
+/// Decodes `bs` in chunks of 8 KiB, passing the characters decoded from each chunk to `emit`. Stops
+/// once `max_chars` characters have been emitted, returning whether the rest was left undecoded.
+fn decode_chunks<F>(
+    enc: EncodingRef,
+    bs: &[u8],
+    trap: DecoderTrap,
+    max_chars: Option<usize>,
+    mut emit: F,
+) -> DetatResult<bool>
+where
+    F: FnMut(&str) -> io::Result<()>,
+{
+    const CHUNK_SIZE: usize = 8192;
+    let mut decoder = enc.raw_decoder();
+    let mut out = String::with_capacity(CHUNK_SIZE);
+    let mut chars = 0;
+    for (i, chunk) in bs.chunks(CHUNK_SIZE).enumerate() {
+        let mut remaining = 0;
+        while remaining < chunk.len() {
+            let (offset, err) = decoder.raw_feed(&chunk[remaining..], &mut out);
+            let unprocessed = remaining + offset;
+            match err {
+                Some(err) => {
+                    // A problem starting in the previous chunk may end before this one.
+                    remaining = (remaining as isize + err.upto).max(0) as usize;
+                    if !trap.trap(&mut *decoder, &chunk[unprocessed.min(remaining)..remaining], &mut out) {
+                        return Err(DetatError::decode(err.cause));
+                    }
+                }
+                None => remaining = chunk.len(),
+            }
+        }
+        if let Some(max_chars) = max_chars {
+            let left = max_chars - chars;
+            chars += out.chars().count();
+            if chars >= max_chars {
+                let end = out.char_indices().nth(left).map_or(out.len(), |(i, _)| i);
+                emit(&out[..end])?;
+                return Ok(end < out.len() || (i + 1) * CHUNK_SIZE < bs.len());
+            }
+        }
+        emit(&out)?;
+        out.clear();
+    }
+    if let Some(err) = decoder.raw_finish(&mut out) {
+        if !trap.trap(&mut *decoder, &[], &mut out) {
+            return Err(DetatError::decode(err.cause));
+        }
+    }
+    emit(&out)?;
+    Ok(false)
+}
+
+/// Merges runs of consecutive windows with the same encoding into one, keeping the lowest confidence of each run
+/// and recording how many windows it spans.
+fn merge_windows(windows: Vec<WindowResult>) -> Vec<WindowResult> {
+    let count = windows.len();
+    let mut merged: Vec<WindowResult> = Vec::new();
+    for window in windows {
+        match merged.last_mut() {
+            Some(last) if last.encoding == window.encoding => {
+                last.confidence = last.confidence.min(window.confidence);
+                last.has_confidence &= window.has_confidence;
+                last.windows = last.windows.map(|n| n + 1);
+            }
+            _ => merged.push(WindowResult { windows: Some(1), ..window }),
+        }
+    }
+    info!("merged {} window(s) into {} segment(s)", count, merged.len());
+    merged
+}
+
+/// Returns the single-byte encoding (the decoding one, or windows-1252 for text already in Unicode) which `s` reads
+/// as UTF-8 text decoded with, judging from nearly all of its non-ASCII bytes in that encoding forming UTF-8
+/// sequences (as in `Ã©` for `é`).
+fn mojibake_misread_encoding(s: &str, enc: EncodingRef) -> Option<&'static str> {
+    let unicode = enc.name().starts_with("utf-");
+    let misread = if unicode { encoding::all::WINDOWS_1252 } else { enc };
+    let bs = misread.encode(s, EncoderTrap::Strict).ok()?;
+    let (mut non_ascii, mut in_sequences, mut i) = (0, 0, 0);
+    while i < bs.len() {
+        let len = match bs[i] {
+            0x00..=0x7F => {
+                i += 1;
+                continue;
+            }
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => 1,
+        };
+        if bs.get(i..i + len).is_some_and(|seq| len > 1 && std::str::from_utf8(seq).is_ok()) {
+            non_ascii += len;
+            in_sequences += len;
+            i += len;
+        } else {
+            non_ascii += 1;
+            i += 1;
+        }
+    }
+    if in_sequences > 0 && in_sequences * 10 >= non_ascii * 9 {
+        Some(misread.whatwg_name().unwrap_or_else(|| misread.name()))
+    } else {
+        None
+    }
+}
+
+/// Guesses the field delimiter of CSV text `s` from its first lines: the candidate found the same (non-zero) number
+/// of times outside quotes on every line, or failing that, the most frequent one.
+fn sniff_csv_delimiter(s: &str) -> Option<char> {
+    const CANDIDATES: [char; 4] = [',', '\t', ';', '|'];
+    const LINES: usize = 10;
+    let counts: Vec<[usize; 4]> = s
+        .lines()
+        .filter(|line| !line.is_empty())
+        .take(LINES)
+        .map(|line| {
+            let mut counts = [0; 4];
+            let mut quoted = false;
+            for c in line.chars() {
+                if c == '"' {
+                    quoted = !quoted;
+                } else if !quoted {
+                    if let Some(i) = CANDIDATES.iter().position(|&d| d == c) {
+                        counts[i] += 1;
+                    }
+                }
+            }
+            counts
+        })
+        .collect();
+    let consistent = (0..CANDIDATES.len())
+        .filter(|&i| counts.iter().all(|c| c[i] > 0 && c[i] == counts[0][i]))
+        .max_by_key(|&i| counts[0][i]);
+    let total = |i: usize| counts.iter().map(|c| c[i]).sum::<usize>();
+    let frequent = (0..CANDIDATES.len()).filter(|&i| total(i) > 0).max_by_key(|&i| total(i));
+    consistent.or(frequent).map(|i| CANDIDATES[i])
+}
+
+/// Swaps each pair of adjacent bytes in `bs`, e.g. to read UTF-16 with the wrong byte order.
+fn swap_byte_pairs(bs: &[u8]) -> DetatResult<Vec<u8>> {
+    if !bs.len().is_multiple_of(2) {
+        return Err(DetatError::invalid_input(
+            InvalidInputErrorKind::OddLength(bs.len()),
+            format!("cannot swap bytes of an odd-length input ({} bytes)", bs.len()),
+        ));
+    }
+    Ok(bs.chunks(2).flat_map(|pair| [pair[1], pair[0]]).collect())
+}
+
+/// Returns the most frequent line ending in `s`, preferring LF on ties and when there are none.
+fn majority_line_ending(s: &str) -> LineEnding {
+    let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {
+                chars.next();
+                crlf += 1;
+            }
+            '\r' => cr += 1,
+            '\n' => lf += 1,
+            _ => {}
+        }
+    }
+    if crlf > lf && crlf >= cr {
+        LineEnding::Crlf
+    } else if cr > lf && cr > crlf {
+        LineEnding::Cr
+    } else {
+        LineEnding::Lf
+    }
+}
+
+/// Replaces every LF, CRLF and CR in `s` with `eol`.
+fn normalize_line_endings(s: &str, eol: &str) -> String {
+    let mut normalized = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                normalized.push_str(eol);
+            }
+            '\n' => normalized.push_str(eol),
+            _ => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// Returns the 1-based numbers of the lines in `s` which end with spaces or tabs.
+fn trailing_whitespace_lines(s: &str) -> Vec<usize> {
+    s.lines().enumerate().filter(|(_, line)| line.ends_with(&[' ', '\t'][..])).map(|(i, _)| i + 1).collect()
+}
+
+fn strip_trailing_whitespace(s: &str) -> String {
+    s.split_inclusive('\n')
+        .map(|line| {
+            let body = line.trim_end_matches(&['\r', '\n'][..]);
+            let eol = &line[body.len()..];
+            format!("{}{}", body.trim_end_matches(&[' ', '\t'][..]), eol)
+        })
+        .collect()
+}
+
+/// Returns the `encoding:` declared in the YAML front matter at the beginning of `bs`.
+fn frontmatter_encoding(bs: &[u8]) -> Option<&str> {
+    let mut lines = bs.split(|&b| b == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+    if lines.next()? != b"---" {
+        return None;
+    }
+    for line in lines.take_while(|&line| line != b"---" && line != b"...") {
+        let line = match std::str::from_utf8(line) {
+            Ok(line) => line,
+            Err(_) => continue,
+        };
+        if let Some(value) = line.strip_prefix("encoding:") {
+            let label = value.trim().trim_matches(&['"', '\''][..]);
+            if encoding_from_label(label).is_some() {
+                return Some(label);
+            }
+            warn!("unknown encoding in front matter: \"{}\"", label);
+            return None;
+        }
+    }
+    None
+}
+
+/// Matches a coding comment, which has to start its line with a comment marker as required by PEP 263 (Emacs and Vim
+/// only look at comments as well) so that e.g. `print("coding: utf-8")` is not taken for one.
+const CODING_COMMENT: &str = r"^[ \t\f]*(?:#|//|;|--|/\*).*?(?:coding[:=][ \t]*|\b(?:fileencoding|fenc)=)([-\w.:]+)";
+
+/// Returns the encoding declared in the first two lines of `bs` by a coding comment such as
+/// `# -*- coding: latin-1 -*-` (Emacs, Python) or `# vim: set fileencoding=utf-8 :` (Vim).
+fn coding_comment_encoding(re: &regex::bytes::Regex, bs: &[u8]) -> Option<&'static str> {
+    for line in bs.split(|&b| b == b'\n').take(2) {
+        let label = match re.captures(line).and_then(|c| c.get(1)).map(|m| std::str::from_utf8(m.as_bytes())) {
+            Some(Ok(label)) => label.trim_end_matches(&['.', ':'][..]),
+            _ => continue,
+        };
+        // Emacs allows an end-of-line conversion suffix, e.g. `utf-8-unix`.
+        let label = ["-unix", "-dos", "-mac"].iter().find_map(|eol| label.strip_suffix(eol)).unwrap_or(label);
+        // Python also accepts codec names such as `latin-1` and `euc_jp`.
+        let candidates = [label.to_string(), label.replace('_', "-"), label.replace(&['-', '_'][..], "")];
+        if let Some(enc) = candidates.iter().find_map(|label| encoding_from_label(label)) {
+            return Some(enc.whatwg_name().unwrap_or_else(|| enc.name()));
+        }
+        warn!("unknown encoding in coding comment: \"{}\"", label);
+        return None;
+    }
+    None
+}
+
+/// Renames `from` to `to`, failing if `to` exists. Files are linked to the new name before the old one is removed,
+/// which fails atomically instead of replacing a file created at `to` meanwhile; directories are checked first.
+#[cfg(unix)]
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    let exists = || io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", to.to_string_lossy()));
+    if fs::symlink_metadata(from)?.is_dir() {
+        if fs::symlink_metadata(to).is_ok() {
+            return Err(exists());
+        }
+        return fs::rename(from, to);
+    }
+    match fs::hard_link(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(exists()),
+        result => result,
+    }?;
+    fs::remove_file(from)
+}
+
+/// Returns `bs` without the incomplete UTF-8 sequence at its end, if any.
+fn trim_partial_utf8(bs: &[u8]) -> &[u8] {
+    // A sequence is at most 4 bytes long, so an incomplete one starts in the last 3.
+    for i in 1..=bs.len().min(3) {
+        let len = match bs[bs.len() - i] {
+            0x80..=0xBF => continue,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        return if len > i { &bs[..bs.len() - i] } else { bs };
+    }
+    bs
+}
+
+/// Returns `bs` without the UTF-8 continuation bytes at its beginning, which belong to a preceding character.
+fn skip_utf8_continuation(bs: &[u8]) -> &[u8] {
+    let n = bs.iter().take(3).take_while(|&&b| (0x80..=0xBF).contains(&b)).count();
+    &bs[n..]
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str().is_empty() || path == Path::new("-")
+}
+
+/// Returns the UTF-16 variant BOM-less `bs` is encoded in, judging from at least half of the code units having a
+/// zero byte on the same side (as ASCII text does) and none on the other, and from it decoding without errors.
+fn utf16_detect(bs: &[u8]) -> Option<&'static str> {
+    if bs.is_empty() || !bs.len().is_multiple_of(2) || bom_encoding(bs).is_some() {
+        return None;
+    }
+    let units = bs.len() / 2;
+    let high_zero = bs.chunks(2).filter(|unit| unit[0] != 0 && unit[1] == 0).count();
+    let low_zero = bs.chunks(2).filter(|unit| unit[0] == 0 && unit[1] != 0).count();
+    let (label, enc): (_, EncodingRef) = if high_zero * 2 >= units && low_zero == 0 {
+        ("UTF-16LE", encoding::all::UTF_16LE)
+    } else if low_zero * 2 >= units && high_zero == 0 {
+        ("UTF-16BE", encoding::all::UTF_16BE)
+    } else {
+        return None;
+    };
+    enc.decode(bs, DecoderTrap::Strict).ok().map(|_| label)
+}
+
+/// Returns the encoding indicated by the byte order mark at the beginning of `bs`.
+fn bom_encoding(bs: &[u8]) -> Option<&'static str> {
+    if bs.starts_with(b"\xFF\xFE\x00\x00") {
+        Some("UTF-32LE")
+    } else if bs.starts_with(b"\x00\x00\xFE\xFF") {
+        Some("UTF-32BE")
+    } else if bs.starts_with(b"\xEF\xBB\xBF") {
+        Some("UTF-8")
+    } else if bs.starts_with(b"\xFF\xFE") {
+        Some("UTF-16LE")
+    } else if bs.starts_with(b"\xFE\xFF") {
+        Some("UTF-16BE")
+    } else {
+        None
+    }
+}
+
+/// Returns true if `bs` consists of a byte order mark and nothing else.
+fn is_bom_only(bs: &[u8]) -> bool {
+    const BOMS: &[&[u8]] = &[b"\xEF\xBB\xBF", b"\xFF\xFE", b"\xFE\xFF", b"\xFF\xFE\x00\x00", b"\x00\x00\xFE\xFF"];
+    BOMS.contains(&bs)
+}
+
+/// Replaces every number in `value` with its string representation (e.g. `read_bytes`, `chardet.confidence`).
+fn stringify_numbers(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Number(n) => *value = serde_json::Value::String(n.to_string()),
+        serde_json::Value::Array(xs) => xs.iter_mut().for_each(stringify_numbers),
+        serde_json::Value::Object(m) => m.values_mut().for_each(stringify_numbers),
+        _ => {}
+    }
+}
+
+/// Replaces every number in the `metadata` and `summary` objects in `value` (e.g. in each of the `files` of
+/// `--report`) with its string representation.
+fn stringify_metadata_numbers(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Array(xs) => xs.iter_mut().for_each(stringify_metadata_numbers),
+        serde_json::Value::Object(m) => {
+            for (key, value) in m.iter_mut() {
+                if key == "metadata" || key == "summary" {
+                    stringify_numbers(value);
+                } else {
+                    stringify_metadata_numbers(value);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads a `--charset-map` file, a JSON object of labels keyed by (case-insensitive) encoding names.
+fn load_charset_map(path: &Path) -> DetatResult<BTreeMap<String, String>> {
+    let json = fs::read_to_string(path)
+        .map_err(|e| DetatError::invalid_opt(format!("cannot read charset map: {}: {}", path.to_string_lossy(), e)))?;
+    let map: BTreeMap<String, String> = serde_json::from_str(&json)
+        .map_err(|e| DetatError::invalid_opt(format!("invalid charset map: {}: {}", path.to_string_lossy(), e)))?;
+    Ok(map.into_iter().map(|(name, label)| (name.to_ascii_lowercase(), label)).collect())
+}
+
+/// Looks up `label` in the WHATWG labels, then in the encodings built into detat.
+fn encoding_from_label(label: &str) -> Option<EncodingRef> {
+    encoding_from_whatwg_label(label).or_else(|| {
+        let label = label.trim().to_ascii_lowercase();
+        if cp437::LABELS.contains(&label.as_str()) {
+            Some(cp437::IBM437)
+        } else {
+            legacy_encoding(&label)
+                .or_else(|| utf32::LABELS.iter().find(|(l, _)| *l == label).map(|&(_, enc)| enc as EncodingRef))
+        }
+    })
+}
+
+#[cfg(feature = "legacy-encodings")]
+fn legacy_encoding(label: &str) -> Option<EncodingRef> {
+    if cp850::LABELS.contains(&label) {
+        Some(cp850::IBM850)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(feature = "legacy-encodings"))]
+fn legacy_encoding(_label: &str) -> Option<EncodingRef> {
+    None
+}
+
+fn missing_feature(feature: &str) -> DetatError {
+    DetatError::invalid_opt(format!("detat was built without the `{}` feature", feature))
+}
+
+fn check_label(label: &str, strict: bool) -> DetatResult<()> {
+    let enc = encoding_from_label(label)
+        .ok_or_else(|| DetatError::invalid_opt(format!("unknown encoding: \"{}\"", label)))?;
+    match enc.whatwg_name() {
+        Some(name) if strict && !name.eq_ignore_ascii_case(label) => Err(DetatError::invalid_opt(format!(
+            "non-canonical encoding label: \"{}\" (canonical: \"{}\")",
+            label, name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Exits quietly if `e` is a broken pipe, as no one is left to read the output anyway: successfully, unless
+/// `errors` inputs have already failed.
+fn exit_on_broken_pipe(e: &DetatError, errors: usize) {
+    if e.is_broken_pipe() {
+        exit(if errors > 0 { 1 } else { 0 })
+    }
+}
+
+fn exit_on_error<T>(result: DetatResult<T>) -> T {
+    match result {
+        Ok(v) => v,
+        Err(e) => {
+            exit_on_broken_pipe(&e, 0);
+            error!("{}", e);
+            exit(1)
+        }
+    }
+}
+
+/// The most `--passes`, the last of which feeds the detector 2 MiB chunks.
+const MAX_PASSES: usize = 16;
+
+fn main() {
+    env_logger::init();
+    let opt = Opt::from_args();
+    if opt.env_dump {
+        let stdout = io::stdout();
+        exit_on_error(EnvDump::new(&opt).print(opt.json, &mut stdout.lock()).map_err(DetatError::from));
+        return;
+    }
+    if cfg!(not(feature = "language")) && opt.language {
+        exit_on_error::<()>(Err(missing_feature("language")));
+    }
+    if cfg!(not(feature = "tar")) && opt.tar {
+        exit_on_error::<()>(Err(missing_feature("tar")));
+    }
+    if cfg!(not(feature = "zip")) && opt.zip {
+        exit_on_error::<()>(Err(missing_feature("zip")));
+    }
+    let from_encoding = if opt.nfo { Some(cp437::IBM437.name.to_string()) } else { opt.from_encoding };
+    if let Some(label) = &from_encoding {
+        exit_on_error(check_label(label, opt.strict_label));
+    }
+    let to_encoding = match &opt.to_encoding {
+        Some(label) => {
+            exit_on_error(check_label(label, opt.strict_label));
+            encoding_from_label(label)
+        }
+        None => None,
+    };
+    if opt.binary_threshold.is_some_and(|t| !(0.0..=1.0).contains(&t)) {
+        exit_on_error::<()>(Err(DetatError::invalid_opt("binary threshold must be in 0..1".to_string())));
+    }
+    if opt.max_errors == Some(0) {
+        exit_on_error::<()>(Err(DetatError::invalid_opt("max errors must be greater than 0".to_string())));
+    }
+    if opt.sample_bytes == Some(0) {
+        exit_on_error::<()>(Err(DetatError::invalid_opt("sample bytes must be greater than 0".to_string())));
+    }
+    if !(1..=MAX_PASSES).contains(&opt.passes) {
+        exit_on_error::<()>(Err(DetatError::invalid_opt(format!("passes must be in 1..{}", MAX_PASSES))));
+    }
+    if opt.min_confident_ratio.is_some_and(|r| !(0.0..=1.0).contains(&r)) {
+        exit_on_error::<()>(Err(DetatError::invalid_opt("confident ratio must be in 0..1".to_string())));
+    }
+    let timeout = opt.timeout.map(|secs| {
+        exit_on_error(
+            Duration::try_from_secs_f64(secs)
+                .map_err(|_| DetatError::invalid_opt(format!("invalid timeout: {} seconds", secs))),
+        )
+    });
+    let labels = opt.encoding_priority.iter().chain(opt.fallback_for.iter());
+    let labels = labels
+        .chain(opt.path_encodings.iter().map(|pe| &pe.encoding))
+        .chain(opt.confidence_thresholds.iter().map(|ct| &ct.label));
+    for label in labels {
+        exit_on_error(check_label(label, opt.strict_label));
+    }
+    if opt.strict_label {
+        if let Some(label) = &opt.fallback_encoding {
+            exit_on_error(check_label(label, true));
+        }
+    }
+    let (fix_trailing_whitespace, line_ending, posix_newline) =
+        (opt.fix_trailing_whitespace, opt.line_ending.is_some(), opt.posix_newline);
+    let enabled = |transform| match transform {
+        Transform::TrailingWhitespace => fix_trailing_whitespace,
+        Transform::LineEnding => line_ending,
+        Transform::PosixNewline => posix_newline,
+    };
+    let order = if opt.pipeline.is_empty() { Transform::ALL.to_vec() } else { opt.pipeline.clone() };
+    if let Some(t) = Transform::ALL.iter().find(|&&t| enabled(t) && !order.contains(&t)) {
+        exit_on_error::<()>(Err(DetatError::invalid_opt(format!("{} is not in --pipeline", t.flag()))));
+    }
+    let pipeline: Vec<Transform> = order.into_iter().filter(|&t| enabled(t)).collect();
+    let charset_map = match &opt.charset_map {
+        Some(path) => exit_on_error(load_charset_map(path)),
+        None => BTreeMap::new(),
+    };
+    let metadata = !opt.content_only;
+    let mut detat = Detat {
+        confidence_min: opt.confidence_min,
+        fallback_encoding: opt.fallback_encoding,
+        // --report collects the JSON records instead of writing them.
+        json: metadata && (opt.json || opt.debug_json || opt.json_seq || opt.report),
+        stat: metadata && (opt.stat || opt.detect_only) || opt.confident_only,
+        allow_binary: opt.allow_binary,
+        decoder_trap: opt.decoder_trap.0,
+        window_size: opt.window_detect,
+        detector: RefCell::new(UniversalDetector::new()),
+        json_numbers_as_strings: opt.json_numbers_as_strings,
+        preview: opt.preview.filter(|_| metadata),
+        path_encodings: opt.path_encodings,
+        from_encoding,
+        confidence_thresholds: opt.confidence_thresholds,
+        skip_bom_only: opt.skip_bom_only,
+        debug_json: metadata && opt.debug_json,
+        min_bytes: opt.min_bytes,
+        dry_run: opt.dry_run,
+        check_trailing_whitespace: opt.check_trailing_whitespace,
+        stream_output: opt.stream_output,
+        language: opt.language,
+        name_style: opt.name_style,
+        frontmatter: opt.frontmatter,
+        passes: opt.passes,
+        json_ordered: opt.json_ordered,
+        utf32: opt.utf32,
+        utf16: opt.utf16,
+        line_ending: opt.line_ending,
+        require_bom: opt.require_bom,
+        forbid_bom: opt.forbid_bom,
+        coding_comment: if opt.respect_coding_comment {
+            Some(regex::bytes::Regex::new(CODING_COMMENT).unwrap())
+        } else {
+            None
+        },
+        emit_meta_sidecar: opt.emit_meta_sidecar,
+        show_fallback_diff: opt.show_fallback_diff,
+        read_limit: if opt.detect_only { Some(opt.sample_bytes.unwrap_or(4096)) } else { None },
+        sample_strategy: opt.sample_strategy,
+        sample_size: opt.sample_bytes.unwrap_or(4096),
+        transforms: Vec::new(),
+        timeout,
+        partial_on_timeout: opt.partial_on_timeout,
+        encoding_priority: opt.encoding_priority,
+        binary_threshold: opt.binary_threshold,
+        binary_sample_bytes: opt.binary_sample_bytes,
+        swap_bytes: opt.swap_bytes,
+        csv_sniff: opt.csv_sniff,
+        max_chars: opt.max_chars,
+        interactive: opt.interactive,
+        pipeline,
+        json_sample: opt.json_sample,
+        ascii_is_utf8: opt.ascii_is_utf8,
+        suspect_mojibake: opt.suspect_mojibake,
+        escape_nul: opt.escape_nul,
+        merge_segments: opt.merge_segments,
+        charset_map,
+        char_stats: opt.char_stats,
+        skip_header_bytes: opt.skip_header_bytes,
+        fail_on_replacement: opt.fail_on_replacement,
+        retain_bom: opt.retain_bom,
+        skip_empty_json: opt.skip_empty_json,
+        fallback_for: opt
+            .fallback_for
+            .iter()
+            .filter_map(|label| encoding_from_label(label))
+            .map(|enc| enc.name())
+            .collect(),
+        filename_style: None,
+        banner_written: Cell::new(false),
+        record_separator: if opt.json_seq { Some(opt.record_separator) } else { None },
+        hex_dump: opt.hex_dump,
+        to_encoding,
+    };
+    if opt.probe {
+        if !exit_on_error(detat.run_probe()) {
+            exit(1)
+        }
+        return;
+    }
+    if opt.from_jsonl {
+        if exit_on_error(detat.run_from_jsonl()) > 0 {
+            exit(1)
+        }
+        return;
+    }
+    let mut paths = opt.paths;
+    if let Some(fd) = opt.fd {
+        // Only names the input (e.g. in --failures-to), which is read from the descriptor itself.
+        paths.push(PathBuf::from(format!("/dev/fd/{}", fd)));
+    } else if paths.is_empty() {
+        paths.push(PathBuf::from(""))
+    }
+    let mut failures = opt.failures_to.map(|path| {
+        let file =
+            exit_on_error(fs::OpenOptions::new().create(true).append(true).open(&path).map_err(DetatError::from));
+        (path, file)
+    });
+    let (print0, failure_reasons) = (opt.print0, opt.failure_reasons);
+    let mut record_failure = |path: &Path, e: &DetatError| {
+        if let Some((failures_to, file)) = failures.as_mut() {
+            let mut line = path.to_string_lossy().into_owned();
+            if failure_reasons {
+                line.push('\t');
+                line.push_str(e.reason());
+            }
+            line.push(if print0 { '\0' } else { '\n' });
+            if let Err(e) = file.write_all(line.as_bytes()) {
+                error!("{}: {}", failures_to.to_string_lossy(), e);
+            }
+        }
+    };
+    let start = Instant::now();
+    let mut errors = 0;
+    let mut skipped = 0;
+    let mut report = Report::default();
+    if opt.recursive {
+        let mut walk = walk::Walk { sort: opt.sort.unwrap_or_default(), ..walk::Walk::default() };
+        for path in paths {
+            if path.is_dir() {
+                walk.push(&path, &opt.excludes);
+            } else {
+                walk.paths.push(path);
+            }
+        }
+        for (path, e) in std::mem::take(&mut walk.errors) {
+            error!("{}: {}", path.to_string_lossy(), e);
+            let e = e.into();
+            record_failure(&path, &e);
+            report.summary.add_error(&e);
+            errors += 1;
+        }
+        info!("excluded {} path(s)", walk.excluded);
+        report.summary.excluded = walk.excluded;
+        paths = walk.paths;
+    }
+    if opt.unique_paths {
+        let mut seen = HashSet::new();
+        let count = paths.len();
+        paths.retain(|path| {
+            if is_stdin(path) {
+                return true;
+            }
+            // Paths that cannot be canonicalized are kept as given and fail when they are read.
+            let key = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+            seen.insert(key)
+        });
+        let duplicates = count - paths.len();
+        info!("collapsed {} duplicate path(s)", duplicates);
+        report.summary.duplicates = duplicates;
+    }
+    detat.filename_style = if opt.no_filename || opt.content_only || opt.to_encoding.is_some() {
+        None
+    } else if opt.headers {
+        Some(FilenameStyle::Header)
+    } else if opt.with_filename {
+        Some(opt.filename_style.unwrap_or(FilenameStyle::Prefix))
+    } else if paths.len() > 1 && opt.split_output.is_none() && !opt.group_by_encoding && io::stdout().is_terminal() {
+        // Redirected output stays the plain concatenation of the inputs, as with cat(1).
+        Some(opt.filename_style.unwrap_or(FilenameStyle::Header))
+    } else {
+        opt.filename_style
+    };
+    let mut groups: Vec<(Option<String>, Vec<u8>)> = Vec::new();
+    // The reference is decoded with the same options as the inputs.
+    let reference = opt.diff_against.as_ref().map(|p| (p.clone(), exit_on_error(detat.decode_path(p)).0));
+    let mut differing = 0;
+    for (i, path) in paths.iter().enumerate() {
+        if opt.max_errors.is_some_and(|max| errors >= max) {
+            skipped = paths.len() - i;
+            break;
+        }
+        let path: &Path = path.as_ref();
+        let result = if let Some(fd) = opt.fd {
+            detat.run_fd(fd).map(|metadata| report.summary.add(&metadata))
+        } else if opt.fix_filenames {
+            detat.fix_filename(path)
+        } else if opt.report {
+            detat.process_path(path, &mut io::sink()).map(|output| {
+                report.summary.add(&output.metadata);
+                report.files.push(output);
+            })
+        } else if opt.group_by_encoding {
+            let mut buf = Vec::new();
+            detat.run_with(path, &mut buf).map(|metadata| {
+                report.summary.add(&metadata);
+                match groups.iter_mut().find(|(encoding, _)| *encoding == metadata.encoding) {
+                    Some((_, group)) => group.extend(buf),
+                    None => groups.push((metadata.encoding, buf)),
+                }
+            })
+        } else if opt.confident_only {
+            detat.run_confident(path).map(|metadata| {
+                if let Some(metadata) = metadata {
+                    report.summary.add(&metadata)
+                }
+            })
+        } else if let Some(reference) = &reference {
+            detat.run_diff_against(path, reference).map(|(same, metadata)| {
+                report.summary.add(&metadata);
+                if !same {
+                    differing += 1
+                }
+            })
+        } else if opt.is_valid_utf8 {
+            detat.run_utf8_validity(path).map(|metadata| report.summary.add(&metadata))
+        } else if opt.tar || opt.zip {
+            let entries = if opt.tar { detat.run_tar(path) } else { detat.run_zip(path) };
+            entries.map(|entries| {
+                for (entry, result) in entries {
+                    match result {
+                        Ok(metadata) => report.summary.add(&metadata),
+                        Err(e) => {
+                            error!("{}: {}", entry, e);
+                            record_failure(Path::new(&entry), &e);
+                            report.summary.add_error(&e);
+                            errors += 1;
+                        }
+                    }
+                }
+            })
+        } else if let Some(prefix) = &opt.split_output {
+            detat.run_split(path, i, paths.len(), prefix).map(|metadata| report.summary.add(&metadata))
+        } else {
+            detat.run(path).map(|metadata| report.summary.add(&metadata))
+        };
+        if let Err(e) = result {
+            exit_on_broken_pipe(&e, errors);
+            error!("{}", e);
+            record_failure(path, &e);
+            report.summary.add_error(&e);
+            errors += 1;
+        }
+    }
+    report.summary.errors = errors;
+    report.summary.elapsed_secs = Some(start.elapsed().as_secs_f64());
+    if report.summary.files > 0 {
+        report.summary.confident_ratio = Some(report.summary.confident as f64 / report.summary.files as f64);
+    }
+    let mut unconfident = false;
+    if opt.min_confident_ratio.is_some() && report.summary.confident_ratio.is_none() {
+        error!("confident ratio: no input was processed");
+        unconfident = true;
+    }
+    if let (Some(min), Some(ratio)) = (opt.min_confident_ratio, report.summary.confident_ratio) {
+        if ratio < min {
+            error!(
+                "confident ratio: {} < {} ({} of {} input(s))",
+                ratio, min, report.summary.confident, report.summary.files
+            );
+            unconfident = true;
+        } else {
+            info!("confident ratio: {} ({} of {} input(s))", ratio, report.summary.confident, report.summary.files);
+        }
+    }
+    if let Some(summary_json) = &opt.summary_json {
+        let mut json = detat.metadata_to_json(&report.summary);
+        json.push(b'\n');
+        if let Err(e) = fs::write(summary_json, json) {
+            error!("{}: {}", summary_json.to_string_lossy(), e);
+            errors += 1;
+        }
+    }
+    let result = if opt.report {
+        detat.print_report(&report)
+    } else if opt.group_by_encoding {
+        detat.print_groups(&groups)
+    } else {
+        Ok(())
+    };
+    if let Err(e) = result {
+        exit_on_broken_pipe(&e, errors);
+        error!("{}", e);
+        errors += 1;
+    }
+    if skipped > 0 {
+        error!("aborted after {} errors ({} input(s) skipped)", errors, skipped);
+    }
+    // Every mode adds its inputs (including archive entries) to the summary, so this covers all of them.
+    let lossy = opt.fail_on_replacement && report.summary.replacements > 0;
+    if lossy {
+        error!("{} replacement character(s) in total", report.summary.replacements);
+    }
+    if differing > 0 {
+        info!("{} input(s) differ from the reference", differing);
+    }
+    if errors > 0 || unconfident || lossy || differing > 0 {
+        exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding::Encoding;
+
+    #[test]
+    fn detect_reader_reads_only_the_sample() {
+        let detat = Detat { sample_size: 1000, ..Detat::default() };
+        let mut r = io::Cursor::new("日本語のテキストです。".repeat(100).into_bytes());
+        let (chardet, confident, read_bytes) = detat.detect_reader(&mut r).unwrap();
+        assert_eq!(chardet.charset, "utf-8");
+        assert!(confident);
+        assert_eq!(read_bytes, 1000);
+        assert_eq!(r.position(), 1000);
+    }
+
+    #[test]
+    fn detect_reader_is_not_confident_below_confidence_min() {
+        let detat = Detat { confidence_min: 1.0, ..Detat::default() };
+        let mut r = io::Cursor::new(b"caf\xe9 cr\xe8me".to_vec());
+        let (chardet, confident, read_bytes) = detat.detect_reader(&mut r).unwrap();
+        assert!(!chardet.charset.is_empty());
+        assert!(!confident);
+        assert_eq!(read_bytes, 10);
+    }
+
+    #[test]
+    fn nul_in_content_round_trips_through_json() {
+        let detat = Detat { json: true, from_encoding: Some("UTF-8".to_string()), ..Detat::default() };
+        let output = detat.output_bytes(b"a\0b", None).unwrap();
+        let json = String::from_utf8(detat.to_json(&output)).unwrap();
+        assert!(json.contains(r#""content":"a\u0000b""#));
+        let parsed: Output = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.content.as_deref(), Some("a\0b"));
+    }
+
+    #[test]
+    fn escape_nul_shows_nul_as_caret_at() {
+        let detat =
+            Detat { json: true, escape_nul: true, from_encoding: Some("UTF-8".to_string()), ..Detat::default() };
+        let output = detat.output_bytes(b"a\0b", None).unwrap();
+        let parsed: Output = serde_json::from_slice(&detat.to_json(&output)).unwrap();
+        assert_eq!(parsed.content.as_deref(), Some("a^@b"));
+    }
+
+    fn transcode(bs: &[u8], to: EncodingRef, retain_bom: bool) -> DetatResult<Vec<u8>> {
+        let detat = Detat { to_encoding: Some(to), retain_bom, ..Detat::default() };
+        let mut w = Vec::new();
+        detat.copy_bytes(bs, None, &mut w)?;
+        Ok(w)
+    }
+
+    #[test]
+    fn retain_bom_translates_utf8_bom_to_utf16() {
+        let utf16le = encoding::all::UTF_16LE;
+        let utf16be = encoding::all::UTF_16BE;
+        assert_eq!(transcode(b"\xEF\xBB\xBFhi", utf16le, true).unwrap(), b"\xFF\xFEh\0i\0");
+        assert_eq!(transcode(b"\xEF\xBB\xBFhi", utf16be, true).unwrap(), b"\xFE\xFF\0h\0i");
+    }
+
+    #[test]
+    fn bom_is_dropped_without_retain_bom() {
+        let utf16le = encoding::all::UTF_16LE;
+        let utf16be = encoding::all::UTF_16BE;
+        assert_eq!(transcode(b"\xEF\xBB\xBFhi", utf16le, false).unwrap(), b"h\0i\0");
+        assert_eq!(transcode(b"\xEF\xBB\xBFhi", utf16be, false).unwrap(), b"\0h\0i");
+        // No BOM is added to inputs without one.
+        assert_eq!(transcode(b"hi", utf16le, true).unwrap(), b"h\0i\0");
+    }
+
+    /// Returns the JSON record `detat` writes for `bs`, or `None` if it writes nothing.
+    fn json_record(detat: &Detat, bs: &[u8]) -> Option<serde_json::Value> {
+        let mut w = Vec::new();
+        detat.copy_as_json(&mut &*bs, None, &mut w).unwrap();
+        if w.is_empty() {
+            None
+        } else {
+            Some(serde_json::from_slice(&w).unwrap())
+        }
+    }
+
+    #[test]
+    fn empty_input_json() {
+        let detat = Detat { json: true, ..Detat::default() };
+        let record = json_record(&detat, b"").unwrap();
+        assert_eq!(record["metadata"]["is_empty"], true);
+        assert_eq!(record["metadata"]["read_bytes"], 0);
+        assert_eq!(record["content"], "");
+    }
+
+    #[test]
+    fn skip_empty_json_omits_empty_input() {
+        let detat = Detat { json: true, skip_empty_json: true, ..Detat::default() };
+        assert!(json_record(&detat, b"").is_none());
+        assert!(json_record(&detat, b"hello\n").is_some());
+    }
+
+    #[test]
+    fn skip_empty_json_requires_a_json_output() {
+        for flag in &["--json", "--json-seq", "--debug-json"] {
+            assert!(Opt::from_iter_safe(&["detat", "--skip-empty-json", flag]).is_ok(), "{}", flag);
+        }
+        assert!(Opt::from_iter_safe(&["detat", "--skip-empty-json"]).is_err());
+    }
+
+    #[test]
+    fn empty_input_has_no_encoding() {
+        let detat = Detat { json: true, ..Detat::default() };
+        let record = json_record(&detat, b"").unwrap();
+        assert_eq!(record["metadata"]["encoding"], serde_json::Value::Null);
+        assert_eq!(record["metadata"]["chardet"]["charset"], "");
+        let detat = Detat { stat: true, ..Detat::default() };
+        let mut w = Vec::new();
+        detat.copy_bytes(b"", Some(Path::new("empty.txt")), &mut w).unwrap();
+        assert_eq!(String::from_utf8(w).unwrap(), "---\nPath: empty.txt\nCharset: (empty file)\n");
+    }
+
+    #[test]
+    fn min_bytes_skips_detection_of_short_input() {
+        let detat = Detat { min_bytes: 4, ..Detat::default() };
+        let mut w = Vec::new();
+        let metadata = detat.copy_bytes(b"hi", None, &mut w).unwrap();
+        assert!(metadata.too_short);
+        assert!(!metadata.fallbacked);
+        assert_eq!(metadata.encoding.as_deref(), Some("UTF-8"));
+        assert_eq!(metadata.chardet.charset, "");
+        assert_eq!(w, b"hi");
+        let detat = Detat { min_bytes: 4, fallback_encoding: Some("windows-1252".to_string()), ..Detat::default() };
+        let metadata = detat.copy_bytes(b"\xE9!", None, &mut w).unwrap();
+        assert!(metadata.too_short && metadata.fallbacked);
+        assert_eq!(metadata.encoding.as_deref(), Some("windows-1252"));
+    }
+
+    #[test]
+    fn to_encoding_transcodes_without_metadata() {
+        let sjis = encoding::all::WINDOWS_31J.encode("日本語\n", EncoderTrap::Strict).unwrap();
+        let detat = Detat {
+            from_encoding: Some("shift_jis".to_string()),
+            to_encoding: Some(encoding::all::UTF_8),
+            ..Detat::default()
+        };
+        let mut w = Vec::new();
+        detat.copy_bytes(&sjis, None, &mut w).unwrap();
+        assert_eq!(w, "日本語\n".as_bytes());
+        let detat = Detat { to_encoding: Some(encoding::all::WINDOWS_31J), ..Detat::default() };
+        let mut w = Vec::new();
+        detat.copy_bytes("\u{FEFF}日本語\n".as_bytes(), None, &mut w).unwrap();
+        assert_eq!(w, sjis);
+    }
+
+    #[test]
+    fn to_encoding_fails_on_unmappable_characters() {
+        let detat = Detat {
+            from_encoding: Some("utf-8".to_string()),
+            to_encoding: Some(encoding::all::ISO_8859_1),
+            ..Detat::default()
+        };
+        let mut w = Vec::new();
+        let e = detat.copy_bytes("café 日本".as_bytes(), None, &mut w).unwrap_err();
+        assert_eq!(e.reason(), "encode");
+        assert!(w.is_empty());
+    }
+
+    /// A writer whose reader goes away after `left` bytes.
+    struct ClosingWriter {
+        left: usize,
+    }
+
+    impl Write for ClosingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.left == 0 {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            let n = buf.len().min(self.left);
+            self.left -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn closed_writer_is_a_broken_pipe() {
+        let bs = "hello\n".repeat(10000).into_bytes();
+        for stream_output in [false, true] {
+            let detat = Detat { stream_output, ..Detat::default() };
+            let e = detat.copy_bytes(&bs, None, &mut ClosingWriter { left: 100 }).unwrap_err();
+            assert!(e.is_broken_pipe());
+        }
+        let e = Detat::default().copy_bytes(b"hello", None, &mut ClosingWriter { left: 0 }).unwrap_err();
+        assert!(e.is_broken_pipe());
+    }
+
+    #[test]
+    fn process_path_returns_output() {
+        let path = env::temp_dir().join(format!("detat-process-path-{}.txt", std::process::id()));
+        fs::write(&path, b"caf\xe9 cr\xe8me br\xfbl\xe9e\n").unwrap();
+        let detat = Detat { filename_style: Some(FilenameStyle::Header), ..Detat::default() };
+        let mut w = Vec::new();
+        let result = detat.process_path(&path, &mut w);
+        fs::remove_file(&path).unwrap();
+        let output = result.unwrap();
+        assert_eq!(output.path.as_deref(), path.to_str());
+        assert_eq!(output.metadata.encoding.as_deref(), Some("ISO-8859-1"));
+        assert_eq!(output.metadata.read_bytes, 18);
+        assert_eq!(output.content.as_deref(), Some("café crème brûlée\n"));
+        let header = format!("===== {} (ISO-8859-1) =====\n", path.to_string_lossy());
+        assert_eq!(String::from_utf8(w).unwrap(), header + "café crème brûlée\n");
+    }
+
+    #[test]
+    fn metadata_without_newer_fields_is_read() {
+        let json = r#"{"path":"a.txt","metadata":{"chardet":{"charset":"ascii","confidence":1.0,"language":""},
+            "encoding":"ascii","is_empty":false,"fallbacked":false,"read_bytes":6},"content":"hello\n"}"#;
+        let output: Output = serde_json::from_str(json).unwrap();
+        assert_eq!(output.metadata.encoding.as_deref(), Some("ascii"));
+        assert!(!output.metadata.bom_detected);
+        assert_eq!(output.metadata.read_bytes, 6);
+    }
+
+    #[test]
+    fn content_only_conflicts_with_other_output() {
+        let modes: &[&[&str]] = &[
+            &["--group-by-encoding"],
+            &["--split-output", "out"],
+            &["--report"],
+            &["--hex-dump"],
+            &["--window-detect", "64"],
+        ];
+        for mode in modes {
+            let args = ["detat", "--content-only"].iter().chain(mode.iter());
+            assert!(Opt::from_iter_safe(args).is_err(), "{:?}", mode);
+            assert!(Opt::from_iter_safe(["detat"].iter().chain(mode.iter())).is_ok(), "{:?}", mode);
+        }
+        assert!(Opt::from_iter_safe(&["detat", "--content-only", "--stat", "--json"]).is_ok());
+    }
+
+    #[test]
+    fn ascii_is_utf8_checks_the_whole_input() {
+        let latin1 = b"caf\xe9 creme brulee\n";
+        let detat = Detat { ascii_is_utf8: true, ..Detat::default() };
+        let mut w = Vec::new();
+        let metadata = detat.copy_bytes(b"hello\n", None, &mut w).unwrap();
+        assert_eq!(metadata.encoding.as_deref(), Some("UTF-8"));
+        assert_eq!(metadata.chardet.confidence, 1.0);
+        // The detector is only fed ASCII here, but the input is not.
+        let detats = [
+            Detat { ascii_is_utf8: true, skip_header_bytes: 5, ..Detat::default() },
+            Detat {
+                ascii_is_utf8: true,
+                sample_strategy: Some(SampleStrategy::Head),
+                sample_size: 3,
+                ..Detat::default()
+            },
+        ];
+        for detat in detats.iter() {
+            let mut w = Vec::new();
+            let metadata = detat.copy_bytes(latin1, None, &mut w).unwrap();
+            assert_ne!(metadata.encoding.as_deref(), Some("UTF-8"));
+            assert_eq!(w, "café creme brulee\n".as_bytes());
+        }
+    }
+
+    #[test]
+    fn report_records_write_sidecars() {
+        // --report collects the records `process_path` returns in JSON mode.
+        let path = env::temp_dir().join(format!("detat-report-{}.txt", std::process::id()));
+        fs::write(&path, b"hello\n").unwrap();
+        let detat = Detat { json: true, emit_meta_sidecar: true, ..Detat::default() };
+        let result = detat.process_path(&path, &mut io::sink());
+        let mut sidecar = path.clone().into_os_string();
+        sidecar.push(".meta.json");
+        let written = fs::read(&sidecar);
+        fs::remove_file(&path).unwrap();
+        let _ = fs::remove_file(&sidecar);
+        let output = result.unwrap();
+        assert_eq!(output.content.as_deref(), Some("hello\n"));
+        let metadata: Metadata = serde_json::from_slice(&written.unwrap()).unwrap();
+        assert_eq!(metadata.read_bytes, 6);
+    }
+
+    #[test]
+    fn json_numbers_as_strings_applies_to_all_metadata() {
+        let detat = Detat { json: true, json_numbers_as_strings: true, ..Detat::default() };
+        let output = detat.output_bytes(b"hello\n", None).unwrap();
+        let mut report = Report::default();
+        report.summary.add(&output.metadata);
+        report.files.push(output);
+        let json: serde_json::Value = serde_json::from_slice(&detat.to_json(&report)).unwrap();
+        assert_eq!(json["files"][0]["metadata"]["read_bytes"], "6");
+        assert_eq!(json["summary"]["files"], "1");
+        let json: serde_json::Value =
+            serde_json::from_slice(&detat.metadata_to_json(&report.files[0].metadata)).unwrap();
+        assert_eq!(json["read_bytes"], "6");
+        assert!(json["chardet"]["confidence"].is_string());
+        let json: serde_json::Value = serde_json::from_slice(&detat.metadata_to_json(&report.summary)).unwrap();
+        assert_eq!(json["read_bytes"], "6");
+    }
+
+    #[test]
+    fn debug_json_shows_what_forced_the_encoding() {
+        let detat = Detat { json: true, debug_json: true, frontmatter: true, ..Detat::default() };
+        let output = detat.output_bytes(b"---\nencoding: latin1\n---\ncaf\xe9\n", None).unwrap();
+        let debug = output.debug.unwrap();
+        assert_eq!(debug.forced_encoding.as_deref(), Some("latin1"));
+        assert_eq!(debug.encoding.as_deref(), Some("latin1"));
+        let detat = Detat { json: true, debug_json: true, ..Detat::default() };
+        assert_eq!(detat.output_bytes(b"hello\n", None).unwrap().debug.unwrap().forced_encoding, None);
+    }
+
+    #[test]
+    fn to_encoding_conflicts_with_file_names() {
+        let flags: &[&[&str]] = &[&["-H"], &["--headers"], &["--filename-style", "banner"]];
+        for flag in flags {
+            let args = ["detat", "--to-encoding", "utf-16le"].iter().chain(flag.iter());
+            assert!(Opt::from_iter_safe(args).is_err(), "{:?}", flag);
+        }
+        assert!(Opt::from_iter_safe(&["detat", "--to-encoding", "utf-16le", "--no-filename"]).is_ok());
+    }
+
+    #[test]
+    fn name_style_applies_to_stat() {
+        let stat = |name_style| {
+            let detat = Detat { stat: true, name_style, ..Detat::default() };
+            let mut w = Vec::new();
+            detat.copy_bytes(b"caf\xe9 cr\xe8me br\xfbl\xe9e\n", None, &mut w).unwrap();
+            String::from_utf8(w).unwrap()
+        };
+        assert!(stat(None).contains("Charset: ISO-8859-1\n"));
+        assert!(stat(Some(NameStyle::Lower)).contains("Charset: windows-1252\n"));
+        assert!(stat(Some(NameStyle::Iconv)).contains("Charset: WINDOWS-1252\n"));
+    }
+
+    #[test]
+    fn content_checks_run_with_stat() {
+        let detat = Detat { stat: true, check_trailing_whitespace: true, ..Detat::default() };
+        let mut w = Vec::new();
+        let metadata = detat.copy_bytes(b"hello \nworld\n", Some(Path::new("a.txt")), &mut w).unwrap();
+        assert_eq!(metadata.trailing_whitespace_lines, 1);
+        assert!(detat.check_confidence(&metadata, None).is_err());
+        assert!(String::from_utf8(w).unwrap().starts_with("---\nPath: a.txt\nCharset: ascii\n"));
+        let detat = Detat { stat: true, json: true, char_stats: true, csv_sniff: true, ..Detat::default() };
+        let output = detat.output_bytes(b"a;b\n1;2\n", None).unwrap();
+        assert_eq!(output.metadata.char_stats.unwrap().letters, 2);
+        assert_eq!(output.metadata.csv_delimiter.as_deref(), Some(";"));
+        assert!(output.content.is_none());
+    }
+
+    #[test]
+    fn coding_comment_requires_comment_marker() {
+        let re = regex::bytes::Regex::new(CODING_COMMENT).unwrap();
+        assert_eq!(
+            coding_comment_encoding(&re, b"#!/usr/bin/python\n# -*- coding: latin-1 -*-\n"),
+            Some("windows-1252")
+        );
+        assert_eq!(coding_comment_encoding(&re, b"// vim: set fileencoding=euc-jp :\n"), Some("euc-jp"));
+        assert_eq!(coding_comment_encoding(&re, b"/* coding=utf-8 */\n"), Some("utf-8"));
+        assert_eq!(coding_comment_encoding(&re, b"print(\"coding: latin-1\")\n"), None);
+        assert_eq!(coding_comment_encoding(&re, b"x = 1\ns = 'fileencoding=euc-jp'\n"), None);
+        assert_eq!(coding_comment_encoding(&re, b"\n\n# coding: latin-1\n"), None);
+    }
+
+    #[test]
+    fn decode_chunks_with_and_without_limit() {
+        let enc = encoding::all::UTF_8;
+        // Multi-byte characters straddle the chunk boundaries.
+        let text = "αβγ".repeat(3000);
+        let mut w = Vec::new();
+        decode_to_writer(enc, text.as_bytes(), DecoderTrap::Strict, &mut w).unwrap();
+        assert_eq!(w, text.as_bytes());
+        assert_eq!(
+            decode_limited(enc, text.as_bytes(), DecoderTrap::Strict, 5000).unwrap(),
+            (text[..10000].to_string(), true)
+        );
+        assert_eq!(decode_limited(enc, text.as_bytes(), DecoderTrap::Strict, 9000).unwrap(), (text.clone(), false));
+        assert_eq!(decode_limited(enc, b"abc", DecoderTrap::Strict, 3).unwrap(), ("abc".to_string(), false));
+        assert_eq!(decode_limited(enc, b"abcd", DecoderTrap::Strict, 3).unwrap(), ("abc".to_string(), true));
+        assert!(decode_to_writer(enc, b"abc\xff", DecoderTrap::Strict, &mut Vec::new()).is_err());
+        assert!(decode_limited(enc, b"abc\xff", DecoderTrap::Strict, 10).is_err());
+        assert!(decode_limited(enc, b"abc\xe3\x81", DecoderTrap::Strict, 10).is_err());
+    }
+
+    #[test]
+    fn split_output_paths_sort_in_input_order() {
+        assert_eq!(split_output_path("out", 7, 1), "out.007");
+        assert_eq!(split_output_path("out", 999, 1000), "out.999");
+        assert_eq!(split_output_path("out", 7, 1001), "out.0007");
+        assert_eq!(split_output_path("out", 1000, 1001), "out.1000");
+    }
+
+    #[test]
+    fn split_output_skips_failed_inputs() {
+        let dir = env::temp_dir().join(format!("detat-split-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let prefix = dir.join("out").to_string_lossy().into_owned();
+        let detat = Detat::default();
+        assert!(detat.run_split(&dir.join("missing.txt"), 0, 1, &prefix).is_err());
+        assert!(!Path::new(&split_output_path(&prefix, 0, 1)).exists());
+        fs::write(dir.join("a.txt"), "hello\n").unwrap();
+        detat.run_split(&dir.join("a.txt"), 1, 2, &prefix).unwrap();
+        assert_eq!(fs::read(split_output_path(&prefix, 1, 2)).unwrap(), b"hello\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Compares detecting many small inputs with a fresh detector each (as `chardet::detect` does) against
+    /// resetting the one kept by `Detat`. Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn detector_reuse_timing() {
+        use std::time::{Duration, Instant};
+        let inputs: Vec<Vec<u8>> =
+            (0..20_000).map(|i| format!("{} caf\u{e9} cr\u{e8}me br\u{fb}l\u{e9}e\n", i).into_bytes()).collect();
+        let detat = Detat::default();
+        // The fastest of several interleaved rounds, to keep warm-up and noise out of the comparison.
+        let (mut fresh_time, mut reused_time) = (Duration::MAX, Duration::MAX);
+        for _ in 0..5 {
+            let start = Instant::now();
+            let fresh: Vec<String> = inputs.iter().map(|bs| chardet::detect(bs).0).collect();
+            fresh_time = fresh_time.min(start.elapsed());
+            let start = Instant::now();
+            let reused: Vec<String> = inputs.iter().map(|bs| detat.detect(bs).charset).collect();
+            reused_time = reused_time.min(start.elapsed());
+            assert_eq!(fresh, reused);
+        }
+        println!("{} inputs: fresh detector {:?}, reused detector {:?}", inputs.len(), fresh_time, reused_time);
+    }
+
+    #[test]
+    fn swap_bytes_round_trip() {
+        let text = "hello world\n";
+        let be: Vec<u8> = text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        let detat = Detat { swap_bytes: true, utf16: true, ..Detat::default() };
+        let mut w = Vec::new();
+        let metadata = detat.copy_bytes(&be, None, &mut w).unwrap();
+        assert_eq!(w, text.as_bytes());
+        assert!(metadata.bytes_swapped);
+        assert_eq!(metadata.encoding.as_deref(), Some("UTF-16LE"));
+        let detat = Detat { json: true, json_sample: Some(5), debug_json: true, ..detat };
+        let output = detat.output_bytes(&be, None).unwrap();
+        assert_eq!(output.content.as_deref(), Some(text));
+        assert_eq!(output.sample.as_deref(), Some("hello"));
+        assert!(output.metadata.bytes_swapped);
+        let debug = output.debug.unwrap();
+        assert_eq!(debug.bom, None);
+        assert_eq!(debug.sample_bytes, be.len());
+        assert!(detat.output_bytes(&be[1..], None).is_err());
+    }
+
+    #[test]
+    fn false_flags_are_left_out_of_json() {
+        let detat = Detat { json: true, ..Detat::default() };
+        let record: serde_json::Value =
+            serde_json::from_slice(&detat.to_json(&detat.output_bytes(b"hello\n", None).unwrap())).unwrap();
+        let metadata = record["metadata"].as_object().unwrap();
+        assert_eq!(metadata["fallbacked"], false);
+        assert!(!metadata.contains_key("is_empty") && !metadata.contains_key("bytes_swapped"));
+        let detat = Detat { json: true, max_chars: Some(2), ..Detat::default() };
+        let record: serde_json::Value =
+            serde_json::from_slice(&detat.to_json(&detat.output_bytes(b"hello\n", None).unwrap())).unwrap();
+        assert_eq!(record["metadata"]["truncated"], true);
     }
 }
@@ -0,0 +1,74 @@
+//! Spellings of encoding names used by WHATWG, IANA (MIME) and iconv.
+
+/// (rust-encoding name, WHATWG name, preferred MIME name, iconv name)
+const NAMES: &[(&str, &str, &str, &str)] = &[
+    ("utf-8", "UTF-8", "UTF-8", "UTF-8"),
+    ("utf-16le", "UTF-16LE", "UTF-16LE", "UTF-16LE"),
+    ("utf-16be", "UTF-16BE", "UTF-16BE", "UTF-16BE"),
+    ("utf-32le", "UTF-32LE", "UTF-32LE", "UTF-32LE"),
+    ("utf-32be", "UTF-32BE", "UTF-32BE", "UTF-32BE"),
+    ("ibm866", "IBM866", "IBM866", "CP866"),
+    ("iso-8859-1", "windows-1252", "ISO-8859-1", "ISO-8859-1"),
+    ("iso-8859-2", "ISO-8859-2", "ISO-8859-2", "ISO-8859-2"),
+    ("iso-8859-3", "ISO-8859-3", "ISO-8859-3", "ISO-8859-3"),
+    ("iso-8859-4", "ISO-8859-4", "ISO-8859-4", "ISO-8859-4"),
+    ("iso-8859-5", "ISO-8859-5", "ISO-8859-5", "ISO-8859-5"),
+    ("iso-8859-6", "ISO-8859-6", "ISO-8859-6", "ISO-8859-6"),
+    ("iso-8859-7", "ISO-8859-7", "ISO-8859-7", "ISO-8859-7"),
+    ("iso-8859-8", "ISO-8859-8", "ISO-8859-8", "ISO-8859-8"),
+    ("iso-8859-8-i", "ISO-8859-8-I", "ISO-8859-8-I", "ISO-8859-8"),
+    ("iso-8859-10", "ISO-8859-10", "ISO-8859-10", "ISO-8859-10"),
+    ("iso-8859-13", "ISO-8859-13", "ISO-8859-13", "ISO-8859-13"),
+    ("iso-8859-14", "ISO-8859-14", "ISO-8859-14", "ISO-8859-14"),
+    ("iso-8859-15", "ISO-8859-15", "ISO-8859-15", "ISO-8859-15"),
+    ("iso-8859-16", "ISO-8859-16", "ISO-8859-16", "ISO-8859-16"),
+    ("koi8-r", "KOI8-R", "KOI8-R", "KOI8-R"),
+    ("koi8-u", "KOI8-U", "KOI8-U", "KOI8-U"),
+    ("mac-roman", "macintosh", "macintosh", "MACINTOSH"),
+    ("mac-cyrillic", "x-mac-cyrillic", "x-mac-cyrillic", "MAC-CYRILLIC"),
+    ("windows-874", "windows-874", "windows-874", "CP874"),
+    ("windows-1250", "windows-1250", "windows-1250", "WINDOWS-1250"),
+    ("windows-1251", "windows-1251", "windows-1251", "WINDOWS-1251"),
+    ("windows-1252", "windows-1252", "windows-1252", "WINDOWS-1252"),
+    ("windows-1253", "windows-1253", "windows-1253", "WINDOWS-1253"),
+    ("windows-1254", "windows-1254", "windows-1254", "WINDOWS-1254"),
+    ("windows-1255", "windows-1255", "windows-1255", "WINDOWS-1255"),
+    ("windows-1256", "windows-1256", "windows-1256", "WINDOWS-1256"),
+    ("windows-1257", "windows-1257", "windows-1257", "WINDOWS-1257"),
+    ("windows-1258", "windows-1258", "windows-1258", "WINDOWS-1258"),
+    ("windows-949", "EUC-KR", "EUC-KR", "CP949"),
+    ("euc-jp", "EUC-JP", "EUC-JP", "EUC-JP"),
+    ("iso-2022-jp", "ISO-2022-JP", "ISO-2022-JP", "ISO-2022-JP"),
+    ("windows-31j", "Shift_JIS", "Shift_JIS", "CP932"),
+    ("gbk", "GBK", "GBK", "GBK"),
+    ("gb18030", "gb18030", "GB18030", "GB18030"),
+    ("hz", "HZ-GB-2312", "HZ-GB-2312", "HZ"),
+    ("big5-2003", "Big5", "Big5", "BIG5"),
+    ("ibm437", "IBM437", "IBM437", "CP437"),
+];
+
+/// Returns the (WHATWG, MIME, iconv) spellings for the rust-encoding `name`.
+pub fn lookup(name: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    NAMES.iter().find(|(n, ..)| *n == name).map(|&(_, whatwg, mime, iconv)| (whatwg, mime, iconv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_spellings() {
+        assert_eq!(lookup("utf-8"), Some(("UTF-8", "UTF-8", "UTF-8")));
+        assert_eq!(lookup("iso-8859-1"), Some(("windows-1252", "ISO-8859-1", "ISO-8859-1")));
+        assert_eq!(lookup("windows-31j"), Some(("Shift_JIS", "Shift_JIS", "CP932")));
+        assert_eq!(lookup("ibm437"), Some(("IBM437", "IBM437", "CP437")));
+        assert_eq!(lookup("Shift_JIS"), None);
+    }
+
+    #[test]
+    fn names_are_unique() {
+        for (i, (name, ..)) in NAMES.iter().enumerate() {
+            assert!(NAMES[i + 1..].iter().all(|(other, ..)| other != name), "{}", name);
+        }
+    }
+}
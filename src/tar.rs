@@ -0,0 +1,239 @@
+//! A minimal reader for ustar/GNU/pax tar archives which yields regular files only.
+
+use std::io::{self, Read};
+
+const BLOCK_SIZE: usize = 512;
+
+pub struct Entry {
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+pub struct Archive<R: Read> {
+    r: R,
+    next_path: Option<String>,
+    done: bool,
+}
+
+impl<R: Read> Archive<R> {
+    pub fn new(r: R) -> Archive<R> {
+        Archive { r, next_path: None, done: false }
+    }
+
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        loop {
+            let mut header = [0u8; BLOCK_SIZE];
+            if !read_block(&mut self.r, &mut header)? || header.iter().all(|&b| b == 0) {
+                return Ok(None);
+            }
+            if !verify_checksum(&header) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid tar header checksum"));
+            }
+            // The size is not trusted to allocate, as only as much as the archive actually has is read.
+            let size = parse_size(&header[124..136])?;
+            let mut data = Vec::new();
+            if (&mut self.r).take(size).read_to_end(&mut data)? as u64 != size {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated tar entry"));
+            }
+            let padding = (BLOCK_SIZE as u64 - size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64;
+            io::copy(&mut (&mut self.r).take(padding), &mut io::sink())?;
+            match header[156] {
+                b'L' => self.next_path = Some(cstr(&data)),
+                b'x' => self.next_path = pax_path(&data).or_else(|| self.next_path.take()),
+                b'0' | b'\0' | b'7' => {
+                    let path = self.next_path.take().unwrap_or_else(|| header_path(&header));
+                    if !path.ends_with('/') {
+                        return Ok(Some(Entry { path, data }));
+                    }
+                }
+                _ => self.next_path = None,
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Archive<R> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.next_entry().transpose();
+        if !matches!(entry, Some(Ok(_))) {
+            self.done = true;
+        }
+        entry
+    }
+}
+
+/// Reads a whole block, returning false on a clean EOF before the first byte.
+fn read_block<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated tar header")),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn verify_checksum(header: &[u8]) -> bool {
+    let expected = match parse_octal(&header[148..156]) {
+        Some(n) => n,
+        None => return false,
+    };
+    let actual: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { u64::from(b' ') } else { u64::from(b) })
+        .sum();
+    actual == expected
+}
+
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let s = std::str::from_utf8(field).ok()?.trim_matches(|c| c == '\0' || c == ' ');
+    if s.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(s, 8).ok()
+}
+
+fn parse_size(field: &[u8]) -> io::Result<u64> {
+    let size = if field[0] & 0x80 != 0 {
+        // GNU base-256 encoding for sizes which do not fit in octal.
+        field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x7f), |acc, &b| acc.checked_mul(256).map(|acc| acc | u64::from(b)))
+    } else {
+        parse_octal(field)
+    };
+    size.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid tar entry size"))
+}
+
+fn cstr(bs: &[u8]) -> String {
+    let end = bs.iter().position(|&b| b == 0).unwrap_or(bs.len());
+    String::from_utf8_lossy(&bs[..end]).into_owned()
+}
+
+/// Returns the path of a header, joining the name to the prefix of POSIX ustar headers. Old GNU headers (magic
+/// `"ustar  \0"`) store other fields (e.g. the access time) where the prefix would be.
+fn header_path(header: &[u8]) -> String {
+    let name = cstr(&header[0..100]);
+    if &header[257..263] == b"ustar\0" {
+        let prefix = cstr(&header[345..500]);
+        if !prefix.is_empty() {
+            return format!("{}/{}", prefix, name);
+        }
+    }
+    name
+}
+
+/// Extracts the `path` record from pax extended header data (`"<len> path=<value>\n"`).
+fn pax_path(data: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(data).lines().find_map(|line| {
+        let (_, record) = line.split_once(' ')?;
+        record.strip_prefix("path=").map(|p| p.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a ustar header for a regular file `path` with the raw `size` field.
+    fn header(path: &str, size: &[u8; 12]) -> [u8; BLOCK_SIZE] {
+        let mut header = [0u8; BLOCK_SIZE];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        header[124..136].copy_from_slice(size);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].fill(b' ');
+        let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        header
+    }
+
+    fn octal_size(size: usize) -> [u8; 12] {
+        let mut field = [0u8; 12];
+        field[..11].copy_from_slice(format!("{:011o}", size).as_bytes());
+        field
+    }
+
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut bs = Vec::new();
+        for (path, data) in entries {
+            bs.extend_from_slice(&header(path, &octal_size(data.len())));
+            bs.extend_from_slice(data);
+            bs.resize(bs.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+        }
+        bs.resize(bs.len() + 2 * BLOCK_SIZE, 0);
+        bs
+    }
+
+    #[test]
+    fn parse_size_octal() {
+        assert_eq!(parse_size(b"00000001750\0").unwrap(), 1000);
+        assert_eq!(parse_size(b"        17 \0").unwrap(), 15);
+        assert_eq!(parse_size(b"\0\0\0\0\0\0\0\0\0\0\0\0").unwrap(), 0);
+        assert!(parse_size(b"0000000019\0\0").is_err());
+    }
+
+    #[test]
+    fn parse_size_base256() {
+        let mut field = [0u8; 12];
+        field[0] = 0x80;
+        field[7] = 0x02;
+        field[11] = 0x01;
+        assert_eq!(parse_size(&field).unwrap(), (2 << 32) | 1);
+        let overflow = [0xFFu8; 12];
+        assert!(parse_size(&overflow).is_err());
+    }
+
+    #[test]
+    fn header_path_prefix() {
+        let mut ustar = header("a.txt", &octal_size(0));
+        ustar[345..348].copy_from_slice(b"dir");
+        assert_eq!(header_path(&ustar), "dir/a.txt");
+        let mut gnu = ustar;
+        gnu[257..265].copy_from_slice(b"ustar  \0");
+        gnu[345..357].copy_from_slice(b"14210323235\0");
+        assert_eq!(header_path(&gnu), "a.txt");
+    }
+
+    #[test]
+    fn read_entries() {
+        let bs = archive(&[("a.txt", b"hello"), ("dir/", b""), ("b.txt", &[b'x'; 600])]);
+        let entries: Vec<Entry> = Archive::new(bs.as_slice()).collect::<io::Result<_>>().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].path.as_str(), entries[0].data.as_slice()), ("a.txt", &b"hello"[..]));
+        assert_eq!((entries[1].path.as_str(), entries[1].data.len()), ("b.txt", 600));
+    }
+
+    #[test]
+    fn truncated_header() {
+        let bs = archive(&[("a.txt", b"hello")]);
+        let mut entries = Archive::new(&bs[..100]);
+        assert_eq!(entries.next().unwrap().err().unwrap().kind(), io::ErrorKind::UnexpectedEof);
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn truncated_data() {
+        let bs = archive(&[("a.txt", &[b'x'; 600])]);
+        let mut entries = Archive::new(&bs[..BLOCK_SIZE + 100]);
+        assert_eq!(entries.next().unwrap().err().unwrap().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn huge_size_is_not_allocated() {
+        let mut bs = header("a.txt", b"77777777777\0").to_vec();
+        bs.resize(1024, 0);
+        let mut entries = Archive::new(bs.as_slice());
+        assert_eq!(entries.next().unwrap().err().unwrap().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
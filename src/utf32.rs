@@ -0,0 +1,184 @@
+//! UTF-32, which is neither provided by rust-encoding nor guessed by chardet.
+
+use encoding::{ByteWriter, CodecError, Encoding, RawDecoder, RawEncoder, StringWriter};
+
+pub const LABELS: &[(&str, &Utf32Encoding)] = &[
+    ("utf-32le", UTF_32LE),
+    ("utf-32be", UTF_32BE),
+    ("utf32le", UTF_32LE),
+    ("utf32be", UTF_32BE),
+    ("ucs-4le", UTF_32LE),
+    ("ucs-4be", UTF_32BE),
+];
+
+pub const UTF_32LE: &Utf32Encoding = &Utf32Encoding { big_endian: false };
+pub const UTF_32BE: &Utf32Encoding = &Utf32Encoding { big_endian: true };
+
+#[derive(Clone, Copy)]
+pub struct Utf32Encoding {
+    big_endian: bool,
+}
+
+impl Encoding for Utf32Encoding {
+    fn name(&self) -> &'static str {
+        if self.big_endian {
+            "utf-32be"
+        } else {
+            "utf-32le"
+        }
+    }
+
+    fn raw_encoder(&self) -> Box<dyn RawEncoder> {
+        Box::new(Utf32Encoder { big_endian: self.big_endian })
+    }
+
+    fn raw_decoder(&self) -> Box<dyn RawDecoder> {
+        Box::new(Utf32Decoder { big_endian: self.big_endian, buf: [0; 4], len: 0 })
+    }
+}
+
+/// Returns the UTF-32 variant `bs` is encoded in, judging from the BOM or, failing that, from most code units
+/// having three zero bytes in the same positions (as ASCII text does).
+pub fn detect(bs: &[u8]) -> Option<&'static str> {
+    if bs.starts_with(b"\xFF\xFE\x00\x00") {
+        return Some("UTF-32LE");
+    } else if bs.starts_with(b"\x00\x00\xFE\xFF") {
+        return Some("UTF-32BE");
+    }
+    if bs.is_empty() || !bs.len().is_multiple_of(4) {
+        return None;
+    }
+    let units = bs.len() / 4;
+    let looks_like = |big_endian: bool, zeros: std::ops::Range<usize>| {
+        bs.chunks(4).all(|unit| char::from_u32(code_point(unit, big_endian)).is_some())
+            && bs.chunks(4).filter(|unit| unit[zeros.clone()].iter().all(|&b| b == 0)).count() * 2 >= units
+    };
+    if looks_like(false, 1..4) {
+        Some("UTF-32LE")
+    } else if looks_like(true, 0..3) {
+        Some("UTF-32BE")
+    } else {
+        None
+    }
+}
+
+fn code_point(unit: &[u8], big_endian: bool) -> u32 {
+    let unit = [unit[0], unit[1], unit[2], unit[3]];
+    if big_endian {
+        u32::from_be_bytes(unit)
+    } else {
+        u32::from_le_bytes(unit)
+    }
+}
+
+struct Utf32Decoder {
+    big_endian: bool,
+    buf: [u8; 4],
+    len: usize,
+}
+
+impl RawDecoder for Utf32Decoder {
+    fn from_self(&self) -> Box<dyn RawDecoder> {
+        Box::new(Utf32Decoder { big_endian: self.big_endian, buf: [0; 4], len: 0 })
+    }
+
+    fn raw_feed(&mut self, input: &[u8], output: &mut dyn StringWriter) -> (usize, Option<CodecError>) {
+        output.writer_hint(input.len() / 4);
+        let mut i = 0;
+        while i < input.len() {
+            // Code units may be split across inputs, so they are completed in `buf` first.
+            let take = (4 - self.len).min(input.len() - i);
+            self.buf[self.len..self.len + take].copy_from_slice(&input[i..i + take]);
+            self.len += take;
+            let start = i;
+            i += take;
+            if self.len < 4 {
+                break;
+            }
+            self.len = 0;
+            match char::from_u32(code_point(&self.buf, self.big_endian)) {
+                Some(c) => output.write_char(c),
+                None => {
+                    return (start, Some(CodecError { upto: i as isize, cause: "invalid UTF-32 code point".into() }));
+                }
+            }
+        }
+        (i - self.len.min(i), None)
+    }
+
+    fn raw_finish(&mut self, _output: &mut dyn StringWriter) -> Option<CodecError> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len = 0;
+        Some(CodecError { upto: 0, cause: "incomplete UTF-32 code unit".into() })
+    }
+}
+
+struct Utf32Encoder {
+    big_endian: bool,
+}
+
+impl RawEncoder for Utf32Encoder {
+    fn from_self(&self) -> Box<dyn RawEncoder> {
+        Box::new(Utf32Encoder { big_endian: self.big_endian })
+    }
+
+    fn raw_feed(&mut self, input: &str, output: &mut dyn ByteWriter) -> (usize, Option<CodecError>) {
+        output.writer_hint(input.len() * 4);
+        for c in input.chars() {
+            let unit = if self.big_endian { (c as u32).to_be_bytes() } else { (c as u32).to_le_bytes() };
+            output.write_bytes(&unit);
+        }
+        (input.len(), None)
+    }
+
+    fn raw_finish(&mut self, _output: &mut dyn ByteWriter) -> Option<CodecError> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding::DecoderTrap;
+
+    #[test]
+    fn decode() {
+        assert_eq!(UTF_32LE.decode(b"a\0\0\0\x42\x30\0\0", DecoderTrap::Strict).unwrap(), "a\u{3042}");
+        assert_eq!(UTF_32BE.decode(b"\0\0\0a\0\x01\xF6\x00", DecoderTrap::Strict).unwrap(), "a\u{1F600}");
+    }
+
+    #[test]
+    fn decode_invalid_code_points() {
+        // Beyond U+10FFFF, and a surrogate.
+        let above = b"a\0\0\0\0\0\x11\0b\0\0\0";
+        let surrogate = b"a\0\0\0\0\xD8\0\0b\0\0\0";
+        for bs in [&above[..], &surrogate[..]] {
+            assert!(UTF_32LE.decode(bs, DecoderTrap::Strict).is_err());
+            assert_eq!(UTF_32LE.decode(bs, DecoderTrap::Replace).unwrap(), "a\u{FFFD}b");
+        }
+        assert!(UTF_32BE.decode(b"\0\0\xDF\xFF", DecoderTrap::Strict).is_err());
+        assert!(UTF_32LE.decode(b"a\0\0", DecoderTrap::Strict).is_err());
+    }
+
+    #[test]
+    fn decode_units_split_across_inputs() {
+        let mut decoder = UTF_32LE.raw_decoder();
+        let mut out = String::new();
+        assert_eq!(decoder.raw_feed(b"a\0", &mut out).0, 0);
+        assert_eq!(decoder.raw_feed(b"\0\0b\0\0", &mut out).0, 2);
+        assert!(decoder.raw_feed(b"\0", &mut out).1.is_none());
+        assert!(decoder.raw_finish(&mut out).is_none());
+        assert_eq!(out, "ab");
+    }
+
+    #[test]
+    fn detect_variant() {
+        assert_eq!(detect(b"\xFF\xFE\0\0a\0\0\0"), Some("UTF-32LE"));
+        assert_eq!(detect(b"\0\0\0a\0\0\0b"), Some("UTF-32BE"));
+        assert_eq!(detect(b"a\0\0\0b\0\0\0"), Some("UTF-32LE"));
+        assert_eq!(detect(b"abcdefgh"), None);
+        assert_eq!(detect(b"a\0\0"), None);
+    }
+}
@@ -0,0 +1,264 @@
+//! Recursive traversal of input directories.
+
+use regex::Regex;
+use std::{
+    cmp::Ordering,
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// A glob pattern. Patterns without `/` match any single path component (e.g. `node_modules`, `*.min.js`);
+/// others match the whole path relative to the traversal root, where `**` matches across directories.
+#[derive(Debug)]
+pub struct Glob {
+    regex: Regex,
+    component: bool,
+}
+
+impl Glob {
+    pub fn is_match(&self, relative: &Path) -> bool {
+        if self.component {
+            relative.iter().any(|c| self.regex.is_match(&c.to_string_lossy()))
+        } else {
+            self.regex.is_match(&relative.to_string_lossy())
+        }
+    }
+}
+
+impl FromStr for Glob {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut re = String::from("^");
+        let mut chars = s.trim_start_matches('/').chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        re.push_str("(?:.*/)?");
+                    } else {
+                        re.push_str(".*");
+                    }
+                }
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                '[' => {
+                    re.push('[');
+                    if chars.peek() == Some(&'!') {
+                        chars.next();
+                        re.push('^');
+                    }
+                    for c in chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                        if c == '\\' || c == '[' {
+                            re.push('\\');
+                        }
+                        re.push(c);
+                    }
+                    re.push(']');
+                }
+                _ => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+        Ok(Glob { regex: Regex::new(&re)?, component: !s.trim_start_matches('/').contains('/') })
+    }
+}
+
+/// The order of directory entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortOrder {
+    #[default]
+    Byte,
+    Locale,
+    Natural,
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "byte" => Ok(SortOrder::Byte),
+            "locale" => Ok(SortOrder::Locale),
+            "natural" => Ok(SortOrder::Natural),
+            _ => Err(format!("invalid sort order: {}", s)),
+        }
+    }
+}
+
+impl SortOrder {
+    fn compare(self, a: &OsStr, b: &OsStr) -> Ordering {
+        match self {
+            SortOrder::Byte => a.cmp(b),
+            SortOrder::Locale => collate(&a.to_string_lossy(), &b.to_string_lossy()).then_with(|| a.cmp(b)),
+            SortOrder::Natural => natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()).then_with(|| a.cmp(b)),
+        }
+    }
+}
+
+/// Compares `a` and `b` with runs of digits compared by their numeric values, so that `file2` sorts before
+/// `file10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (ca, cb) = match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) => (ca, cb),
+        };
+        if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let (da, ra) = a.split_at(a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len()));
+            let (db, rb) = b.split_at(b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len()));
+            let (na, nb) = (da.trim_start_matches('0'), db.trim_start_matches('0'));
+            match na.len().cmp(&nb.len()).then_with(|| na.cmp(nb)) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            }
+            a = ra;
+            b = rb;
+        } else {
+            match ca.cmp(&cb) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            }
+            a = &a[ca.len_utf8()..];
+            b = &b[cb.len_utf8()..];
+        }
+    }
+}
+
+/// Compares `a` and `b` with the C library's `strcoll` in the collation of the environment's locale.
+#[cfg(unix)]
+fn collate(a: &str, b: &str) -> Ordering {
+    use std::{
+        ffi::CString,
+        os::raw::{c_char, c_int},
+        sync::Once,
+    };
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const LC_COLLATE: c_int = 3;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const LC_COLLATE: c_int = 1;
+    extern "C" {
+        fn setlocale(category: c_int, locale: *const c_char) -> *mut c_char;
+        fn strcoll(a: *const c_char, b: *const c_char) -> c_int;
+    }
+    static INIT: Once = Once::new();
+    // SAFETY: The locale is set once, before any comparison, and both strings are NUL-terminated.
+    INIT.call_once(|| unsafe {
+        setlocale(LC_COLLATE, b"\0".as_ptr() as *const c_char);
+    });
+    match (CString::new(a), CString::new(b)) {
+        (Ok(a), Ok(b)) => unsafe { strcoll(a.as_ptr(), b.as_ptr()) }.cmp(&0),
+        _ => a.cmp(b),
+    }
+}
+
+#[cfg(not(unix))]
+fn collate(a: &str, b: &str) -> Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+#[derive(Debug, Default)]
+pub struct Walk {
+    pub paths: Vec<PathBuf>,
+    pub excluded: usize,
+    pub errors: Vec<(PathBuf, io::Error)>,
+    pub sort: SortOrder,
+}
+
+impl Walk {
+    /// Collects the files under `root` in `sort` order, skipping paths matched by `excludes`.
+    pub fn push(&mut self, root: &Path, excludes: &[Glob]) {
+        self.walk(root, Path::new(""), excludes)
+    }
+
+    fn walk(&mut self, root: &Path, relative: &Path, excludes: &[Glob]) {
+        let dir = root.join(relative);
+        let mut entries: Vec<_> = match fs::read_dir(&dir).and_then(|rd| rd.collect::<io::Result<Vec<_>>>()) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.errors.push((dir, e));
+                return;
+            }
+        };
+        entries.sort_by(|a, b| self.sort.compare(&a.file_name(), &b.file_name()));
+        for entry in entries {
+            let relative = relative.join(entry.file_name());
+            if excludes.iter().any(|glob| glob.is_match(&relative)) {
+                self.excluded += 1;
+                continue;
+            }
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => self.walk(root, &relative, excludes),
+                Ok(_) => self.paths.push(root.join(&relative)),
+                Err(e) => self.errors.push((root.join(&relative), e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(s: &str) -> Glob {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn natural_order() {
+        let mut names = vec!["file10", "file2", "file1", "file02", "a", "file1b"];
+        names.sort_by(|a, b| SortOrder::Natural.compare(OsStr::new(a), OsStr::new(b)));
+        assert_eq!(names, ["a", "file1", "file1b", "file02", "file2", "file10"]);
+        assert_eq!(natural_cmp("x007", "x7"), Ordering::Equal);
+        assert_eq!(SortOrder::Natural.compare(OsStr::new("x007"), OsStr::new("x7")), Ordering::Less);
+    }
+
+    #[test]
+    fn locale_order() {
+        assert_eq!(collate("apple", "banana"), Ordering::Less);
+        assert_eq!(collate("banana", "apple"), Ordering::Greater);
+        assert_eq!(collate("same", "same"), Ordering::Equal);
+        // Names which cannot be passed to strcoll are compared byte by byte.
+        assert_eq!(collate("a\0b", "a\0a"), Ordering::Greater);
+        assert_eq!(SortOrder::Locale.compare(OsStr::new("same"), OsStr::new("same")), Ordering::Equal);
+    }
+
+    #[test]
+    fn glob_matches() {
+        assert!(glob("*.min.js").is_match(Path::new("lib/app.min.js")));
+        assert!(!glob("*.min.js").is_match(Path::new("lib/app.js")));
+        assert!(glob("node_modules").is_match(Path::new("a/node_modules/b.txt")));
+        assert!(glob("/docs/*.md").is_match(Path::new("docs/a.md")));
+        assert!(!glob("docs/*.md").is_match(Path::new("docs/sub/a.md")));
+        assert!(glob("docs/**/*.md").is_match(Path::new("docs/sub/a.md")));
+        assert!(glob("docs/**/*.md").is_match(Path::new("docs/a.md")));
+        assert!(glob("file?.[ch]").is_match(Path::new("file1.c")));
+        assert!(!glob("file?.[!ch]").is_match(Path::new("file1.c")));
+        assert!(glob("a+b(1).txt").is_match(Path::new("a+b(1).txt")));
+    }
+
+    #[test]
+    fn walk_excludes() {
+        let root = std::env::temp_dir().join(format!("detat-walk-{}", std::process::id()));
+        fs::create_dir_all(root.join("sub/skip")).unwrap();
+        for file in ["b.txt", "a.log", "sub/c.txt", "sub/skip/d.txt"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let mut walk = Walk::default();
+        walk.push(&root, &[glob("*.log"), glob("sub/skip")]);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(walk.paths, [root.join("b.txt"), root.join("sub/c.txt")]);
+        assert_eq!(walk.excluded, 2);
+        assert!(walk.errors.is_empty());
+    }
+}
@@ -0,0 +1,465 @@
+//! A minimal reader for zip archives with stored or deflated entries which yields files only.
+
+use std::io;
+
+use encoding::{DecoderTrap, Encoding};
+
+use crate::cp437;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const END_SIZE: usize = 22;
+
+/// Bit 11 of the general purpose flags, set when the entry name is UTF-8 rather than CP437.
+const FLAG_UTF8: u16 = 1 << 11;
+const FLAG_ENCRYPTED: u16 = 1;
+
+pub struct Entry {
+    pub path: String,
+    pub utf8_name: bool,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    offset: usize,
+}
+
+pub struct Archive {
+    bs: Vec<u8>,
+}
+
+impl Archive {
+    pub fn new(bs: Vec<u8>) -> Archive {
+        Archive { bs }
+    }
+
+    /// Returns the file entries listed in the central directory, in order.
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        let end = self.find_end()?;
+        let count = usize::from(le16(&self.bs, end + 10)?);
+        let offset = le32(&self.bs, end + 16)?;
+        if count == 0xFFFF || offset == 0xFFFF_FFFF {
+            return Err(invalid_data("ZIP64 archives are not supported"));
+        }
+        let mut pos = offset as usize;
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            if le32(&self.bs, pos)? != CENTRAL_HEADER_SIGNATURE {
+                return Err(invalid_data("invalid zip central directory header"));
+            }
+            let flags = le16(&self.bs, pos + 8)?;
+            let name_len = usize::from(le16(&self.bs, pos + 28)?);
+            let extra_len = usize::from(le16(&self.bs, pos + 30)?);
+            let comment_len = usize::from(le16(&self.bs, pos + 32)?);
+            let name = slice(&self.bs, pos + 46, name_len)?;
+            let utf8_name = flags & FLAG_UTF8 != 0;
+            let entry = Entry {
+                path: decode_name(name, utf8_name),
+                utf8_name,
+                flags,
+                method: le16(&self.bs, pos + 10)?,
+                crc: le32(&self.bs, pos + 16)?,
+                compressed_size: le32(&self.bs, pos + 20)? as usize,
+                size: le32(&self.bs, pos + 24)? as usize,
+                offset: le32(&self.bs, pos + 42)? as usize,
+            };
+            if !entry.path.ends_with('/') {
+                entries.push(entry);
+            }
+            pos += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(entries)
+    }
+
+    /// Returns the uncompressed data of `entry`, checking its CRC-32.
+    pub fn data(&self, entry: &Entry) -> io::Result<Vec<u8>> {
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            return Err(invalid_data("encrypted zip entries are not supported"));
+        }
+        if le32(&self.bs, entry.offset)? != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid_data("invalid zip local header"));
+        }
+        let name_len = usize::from(le16(&self.bs, entry.offset + 26)?);
+        let extra_len = usize::from(le16(&self.bs, entry.offset + 28)?);
+        let compressed = slice(&self.bs, entry.offset + 30 + name_len + extra_len, entry.compressed_size)?;
+        let data = match entry.method {
+            0 => compressed.to_vec(),
+            8 => inflate(compressed, entry.size)?,
+            method => return Err(invalid_data(&format!("unsupported zip compression method: {}", method))),
+        };
+        if data.len() != entry.size || crc32(&data) != entry.crc {
+            return Err(invalid_data("zip entry CRC mismatch"));
+        }
+        Ok(data)
+    }
+
+    /// Finds the end of central directory record, which may be followed by a comment of up to 64 KiB.
+    fn find_end(&self) -> io::Result<usize> {
+        let last = self.bs.len().checked_sub(END_SIZE).ok_or_else(|| invalid_data("not a zip archive"))?;
+        let first = last.saturating_sub(0xFFFF);
+        (first..=last)
+            .rev()
+            .find(|&i| le32(&self.bs, i).ok() == Some(END_SIGNATURE))
+            .ok_or_else(|| invalid_data("not a zip archive"))
+    }
+}
+
+fn decode_name(name: &[u8], utf8: bool) -> String {
+    if utf8 || name.is_ascii() {
+        String::from_utf8_lossy(name).into_owned()
+    } else {
+        cp437::IBM437.decode(name, DecoderTrap::Replace).unwrap_or_default()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn too_large() -> io::Error {
+    invalid_data("zip entry larger than its declared size")
+}
+
+fn slice(bs: &[u8], pos: usize, len: usize) -> io::Result<&[u8]> {
+    bs.get(pos..pos.saturating_add(len)).ok_or_else(|| invalid_data("truncated zip archive"))
+}
+
+fn le16(bs: &[u8], pos: usize) -> io::Result<u16> {
+    slice(bs, pos, 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn le32(bs: &[u8], pos: usize) -> io::Result<u32> {
+    slice(bs, pos, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+fn crc32(bs: &[u8]) -> u32 {
+    !bs.iter().fold(!0u32, |c, &b| CRC_TABLE[((c ^ u32::from(b)) & 0xFF) as usize] ^ (c >> 8))
+}
+
+const LENGTH_BASE: [u16; 29] =
+    [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] =
+    [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    bs: &'a [u8],
+    pos: usize,
+    bits: u32,
+    len: u32,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, n: u32) -> io::Result<u32> {
+        while self.len < n {
+            let b = *self.bs.get(self.pos).ok_or_else(|| invalid_data("truncated deflate stream"))?;
+            self.pos += 1;
+            self.bits |= u32::from(b) << self.len;
+            self.len += 8;
+        }
+        let v = self.bits & ((1 << n) - 1);
+        self.bits >>= n;
+        self.len -= n;
+        Ok(v)
+    }
+
+    /// Discards the rest of the current byte. Bytes are only loaded when needed, so fewer than 8 bits are left.
+    fn align(&mut self) {
+        self.bits = 0;
+        self.len = 0;
+    }
+}
+
+/// A canonical Huffman code, decoded bit by bit as in zlib's `puff`.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[usize::from(offsets[usize::from(len)])] = symbol as u16;
+                offsets[usize::from(len)] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, r: &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in self.counts[1..].iter() {
+            code |= r.read(1)? as i32;
+            let count = i32::from(count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("invalid deflate code"))
+    }
+}
+
+/// Decompresses a raw deflate stream (RFC 1951), giving up as soon as it produces more than `limit` bytes (the
+/// declared size of the entry) rather than trusting it to be no deflate bomb.
+fn inflate(bs: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut r = BitReader { bs, pos: 0, bits: 0, len: 0 };
+    loop {
+        let last = r.read(1)? == 1;
+        match r.read(2)? {
+            0 => {
+                r.align();
+                let len = le16(r.bs, r.pos)?;
+                if le16(r.bs, r.pos + 2)? != !len {
+                    return Err(invalid_data("invalid deflate stored block length"));
+                }
+                let len = usize::from(len);
+                if out.len() + len > limit {
+                    return Err(too_large());
+                }
+                out.extend_from_slice(slice(r.bs, r.pos + 4, len)?);
+                r.pos += 4 + len;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                inflate_block(&mut r, &Huffman::new(&lengths), &Huffman::new(&[5; 30]), limit, &mut out)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut r)?;
+                inflate_block(&mut r, &literals, &distances, limit, &mut out)?;
+            }
+            _ => return Err(invalid_data("invalid deflate block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn read_dynamic_codes(r: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = r.read(5)? as usize + 257;
+    let distance_count = r.read(5)? as usize + 1;
+    let code_length_count = r.read(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for &i in CODE_LENGTH_ORDER[..code_length_count].iter() {
+        code_lengths[i] = r.read(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (len, repeat) = match code_lengths.decode(r)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or_else(|| invalid_data("invalid deflate code lengths"))?, 3 + r.read(2)?),
+            17 => (0, 3 + r.read(3)?),
+            _ => (0, 11 + r.read(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(len, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(invalid_data("invalid deflate code lengths"));
+    }
+    let distances = lengths.split_off(literal_count);
+    Ok((Huffman::new(&lengths), Huffman::new(&distances)))
+}
+
+fn inflate_block(
+    r: &mut BitReader,
+    literals: &Huffman,
+    distances: &Huffman,
+    limit: usize,
+    out: &mut Vec<u8>,
+) -> io::Result<()> {
+    loop {
+        if out.len() > limit {
+            return Err(too_large());
+        }
+        let symbol = usize::from(literals.decode(r)?);
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err(invalid_data("invalid deflate length"));
+                }
+                let len = usize::from(LENGTH_BASE[i]) + r.read(u32::from(LENGTH_EXTRA[i]))? as usize;
+                let i = usize::from(distances.decode(r)?);
+                if i >= DISTANCE_BASE.len() {
+                    return Err(invalid_data("invalid deflate distance"));
+                }
+                let distance = usize::from(DISTANCE_BASE[i]) + r.read(u32::from(DISTANCE_EXTRA[i]))? as usize;
+                let start = out.len().checked_sub(distance).ok_or_else(|| invalid_data("invalid deflate distance"))?;
+                // The copy may overlap what it produces, so it goes byte by byte.
+                for j in 0..len {
+                    out.push(out[start + j]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a zip archive of a single entry `name` with `compressed` data of `size` bytes when uncompressed.
+    fn archive(name: &str, method: u16, crc: u32, compressed: &[u8], size: usize) -> Vec<u8> {
+        let mut fields = Vec::new();
+        for v in [method, 0, 0] {
+            fields.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [crc, compressed.len() as u32, size as u32] {
+            fields.extend_from_slice(&v.to_le_bytes());
+        }
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        let mut bs = LOCAL_HEADER_SIGNATURE.to_le_bytes().to_vec();
+        bs.extend_from_slice(&[20, 0, 0, 0]);
+        bs.extend_from_slice(&fields);
+        bs.extend_from_slice(name.as_bytes());
+        bs.extend_from_slice(compressed);
+        let central = bs.len();
+        bs.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+        bs.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+        bs.extend_from_slice(&fields);
+        bs.extend_from_slice(&[0; 10]);
+        bs.extend_from_slice(&0u32.to_le_bytes());
+        bs.extend_from_slice(name.as_bytes());
+        let central_size = bs.len() - central;
+        bs.extend_from_slice(&END_SIGNATURE.to_le_bytes());
+        bs.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+        bs.extend_from_slice(&(central_size as u32).to_le_bytes());
+        bs.extend_from_slice(&(central as u32).to_le_bytes());
+        bs.extend_from_slice(&[0, 0]);
+        bs
+    }
+
+    /// The lines compressed into `DYNAMIC` by zlib.
+    fn dynamic_data() -> Vec<u8> {
+        (0..20).flat_map(|i| format!("{} aaaaaaaaaaaabbbbbbbbbbbbcccc\n", i).into_bytes()).collect()
+    }
+
+    const STORED: &[u8] =
+        &[0x01, 0x0c, 0x00, 0xf3, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64];
+    const FIXED: &[u8] = &[0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00];
+    const DYNAMIC: &[u8] = &[
+        0x85, 0xd0, 0xb1, 0x01, 0x80, 0x20, 0x00, 0xc4, 0xc0, 0xde, 0x29, 0x18, 0x81, 0xa8, 0xa8, 0x8c, 0xa3, 0xee,
+        0xbf, 0x03, 0x94, 0x54, 0x9f, 0xb4, 0xe9, 0xae, 0x96, 0x77, 0xe9, 0x5b, 0xfa, 0x67, 0x1b, 0x79, 0xef, 0x79,
+        0x1f, 0x79, 0x9f, 0x79, 0xb7, 0xbc, 0xaf, 0xbc, 0xef, 0xbc, 0x9f, 0xbc, 0xbb, 0xb0, 0x54, 0xf9, 0xe2, 0x86,
+        0xc0, 0x21, 0x72, 0x08, 0x1d, 0x62, 0x87, 0xe0, 0x21, 0x7a, 0x08, 0x1f, 0xe2, 0x37, 0x00,
+    ];
+
+    #[test]
+    fn inflate_stored() {
+        assert_eq!(inflate(STORED, 12).unwrap(), b"hello, world");
+    }
+
+    #[test]
+    fn inflate_fixed() {
+        assert_eq!(inflate(FIXED, 17).unwrap(), b"hello hello hello");
+    }
+
+    #[test]
+    fn inflate_dynamic() {
+        let data = dynamic_data();
+        assert_eq!(inflate(DYNAMIC, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn inflate_corrupt() {
+        // A final block of the reserved type 3.
+        assert!(inflate(&[0x07], 100).is_err());
+        assert!(inflate(&FIXED[..5], 17).is_err());
+        assert!(inflate(&DYNAMIC[..30], 630).is_err());
+        // A stored block whose length does not match its complement, cut short.
+        assert!(inflate(&STORED[..10], 12).is_err());
+    }
+
+    #[test]
+    fn inflate_stored_length_mismatch() {
+        let mut bs = STORED.to_vec();
+        bs[3] ^= 1;
+        assert!(inflate(&bs, 12).is_err());
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn inflate_stops_at_limit() {
+        assert!(inflate(STORED, 11).is_err());
+        assert!(inflate(FIXED, 5).is_err());
+        assert!(inflate(DYNAMIC, 100).is_err());
+    }
+
+    #[test]
+    fn read_entries() {
+        let data = dynamic_data();
+        let bs = archive("a.txt", 8, crc32(&data), DYNAMIC, data.len());
+        let archive = Archive::new(bs);
+        let entries = archive.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "a.txt");
+        assert_eq!(archive.data(&entries[0]).unwrap(), data);
+    }
+
+    #[test]
+    fn crc_mismatch() {
+        let bs = archive("a.txt", 0, crc32(b"hello") ^ 1, b"hello", 5);
+        let archive = Archive::new(bs);
+        let entries = archive.entries().unwrap();
+        assert!(archive.data(&entries[0]).is_err());
+    }
+
+    #[test]
+    fn truncated_archive() {
+        let bs = archive("a.txt", 0, crc32(b"hello"), b"hello", 5);
+        assert!(Archive::new(bs[..bs.len() - 1].to_vec()).entries().is_err());
+        assert!(Archive::new(bs[..10].to_vec()).entries().is_err());
+    }
+}